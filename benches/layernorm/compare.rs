@@ -0,0 +1,57 @@
+#![allow(non_snake_case)]
+//Each variant's own bench target is included as a module, only its kernel is used here:
+//validation and timing both run on this target's TIMER, the modules' are never created
+#[allow(dead_code)]
+mod naive;
+#[allow(dead_code)]
+mod naive_onepass;
+#[allow(dead_code)]
+mod naive_vectorized;
+#[allow(dead_code)]
+mod naive_vectorized_onepass;
+#[allow(dead_code)]
+mod welford_scalar;
+#[allow(dead_code)]
+mod welford_vectorized;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use wgpu_bencher::{
+    shape, Autotuner, BenchConfig, CPUTensor, Comparison, GPUHandle, KernelTensor, WgpuTimer,
+};
+
+lazy_static::lazy_static! {
+    pub static ref TIMER: WgpuTimer = WgpuTimer::new(pollster::block_on(async {
+        GPUHandle::new().await.unwrap()
+    }));
+}
+
+pub fn benchmark(c: &mut Criterion<&WgpuTimer>) {
+    let (M, N) = (2048, 512);
+    let eps = 1e-5;
+    let tensors = vec![
+        KernelTensor::input(CPUTensor::randn::<f32>(shape![1, M, N])),
+        KernelTensor::input(CPUTensor::randn::<f32>(shape![N])),
+        KernelTensor::input(CPUTensor::randn::<f32>(shape![N])),
+        KernelTensor::output(CPUTensor::zeros::<f32>(shape![1, M, N])),
+    ];
+    let welford_vectorized = Autotuner::new(&TIMER)
+        .tune(&welford_vectorized::LayerNorm::new(eps, 32))
+        .unwrap();
+
+    let mut comparison = Comparison::new(c, &TIMER, "LayerNorm", tensors, BenchConfig::default());
+    comparison
+        .baseline(naive::LayerNormBench::new(M, N, eps))
+        .bench(naive_onepass::LayerNorm::new(eps))
+        .bench(naive_vectorized::LayerNorm::new(eps))
+        .bench(naive_vectorized_onepass::LayerNorm::new(eps))
+        .bench(welford_scalar::LayerNorm::new(eps))
+        .bench(welford_vectorized);
+    comparison.finish();
+}
+
+criterion_group!(
+    name = bench;
+    config = Criterion::default().with_measurement(&*TIMER);
+    targets = benchmark
+);
+criterion_main!(bench);
@@ -1,14 +1,11 @@
 #![allow(non_snake_case)]
 use encase::ShaderType;
-use inline_python::{python, Context};
-use numpy::PyArrayDyn;
-use pyo3::Python;
-use smallvec::smallvec;
 
-use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use criterion::{criterion_group, criterion_main, Criterion};
 use wgpu_bencher::{
-    dispatch_validate, shape, wgc, wgs, CPUTensor, GPUHandle, KernelBench, KernelContextExt,
-    OpMetadata, WgpuTimer, Workload,
+    dispatch_validate, kernel_tera, reference, render_wgsl, shape, wgs, CPUTensor, Fixture,
+    GPUHandle, KernelBench, KernelTensor, KernelWork, OpMetadata, TensorRole, WgpuTimer, Workload,
+    LAYERNORM_FLOPS,
 };
 
 lazy_static::lazy_static! {
@@ -42,30 +39,33 @@ impl KernelBench for LayerNorm {
         "LayerNormOnePass"
     }
 
-    fn source(workload: &Workload) -> String {
-        let mut tera = tera::Tera::default();
-        let mut context = tera::Context::new();
+    fn source(&self, workload: &Workload) -> String {
+        let mut tera = kernel_tera();
         tera.add_raw_template(
             Self::name(),
             include_str!("../../kernels/layernorm/onepass_scalar.wgsl"),
         )
         .unwrap();
-        context.insert_workload(workload);
-        tera.render(Self::name(), &context).unwrap()
+        render_wgsl(&tera, Self::name(), &self.context(workload)).unwrap()
     }
 
-    fn tensors() -> Vec<CPUTensor> {
+    fn tensors(&self) -> Vec<KernelTensor> {
         let input = CPUTensor::randn::<f32>(shape![1, PROB_M, PROB_N]);
         let scale = CPUTensor::randn::<f32>(shape![PROB_N]);
         let bias = CPUTensor::randn::<f32>(shape![PROB_N]);
         let output = CPUTensor::zeros::<f32>(shape![1, PROB_M, PROB_N]);
-        vec![input, scale, bias, output]
+        vec![
+            KernelTensor::input(input),
+            KernelTensor::input(scale),
+            KernelTensor::input(bias),
+            KernelTensor::output(output),
+        ]
     }
 
-    fn workload(tensors: &[CPUTensor]) -> Workload {
-        let input = &tensors[0];
-        let [_B, M, _N] = input.shape().try_into().unwrap();
-        Workload::new(wgs![128, 1, 1], wgc![M as _, 1, 1])
+    fn workload(&self, tensors: &[CPUTensor]) -> Workload {
+        Workload::builder(tensors[0].shape(), wgs![128, 1, 1])
+            .foldable()
+            .rows()
     }
 
     fn metadata(&self, tensors: &[CPUTensor]) -> Self::Metadata {
@@ -74,32 +74,29 @@ impl KernelBench for LayerNorm {
         LayerNormMeta::new(M as _, N as _, (N / 4) as _, self.eps)
     }
 
-    fn validate(&self, tensors: &[CPUTensor]) {
+    fn work(&self, tensors: &[CPUTensor]) -> KernelWork {
+        KernelWork::per_element(tensors, LAYERNORM_FLOPS)
+    }
+
+    fn fixture_key(&self) -> String {
+        format!("eps{:e}", self.eps)
+    }
+
+    fn validate(&self, handle: &GPUHandle, tensors: &[CPUTensor], roles: &[TensorRole]) {
         let (input, scale, bias) = (&tensors[0], &tensors[1], &tensors[2]);
-        let ground = Python::with_gil(|py| {
-            let (py_input, py_scale, py_bias) = (
-                input.to_py::<f32>(&py),
-                scale.to_py::<f32>(&py),
-                bias.to_py::<f32>(&py),
-            );
-            let result: Context = python! {
-                import torch
-                import torch.nn.functional as F
-
-                (input, scale, bias) = (torch.from_numpy('py_input), torch.from_numpy('py_scale), torch.from_numpy('py_bias))
-                result = F.layer_norm(input, (input.shape[-1],), weight=scale, bias=bias).numpy()
-            };
-            CPUTensor::from(result.get_with_gil::<&PyArrayDyn<f32>>(py, "result"))
-        });
-        let mut gpu_tensors = dispatch_validate(TIMER.handle(), self);
-        let cpu_result = gpu_tensors.remove(3).into_cpu(TIMER.handle()).unwrap();
+        let ground = Fixture::for_kernel(self, tensors)
+            .outputs_or_else(tensors, || {
+                vec![reference::layernorm(input, scale, bias, self.eps)]
+            })
+            .unwrap()
+            .remove(0);
+        let cpu_result = dispatch_validate(handle, self, tensors, roles).remove(0);
         ground.all_close(&cpu_result, 1e-4, 1e-4).unwrap();
     }
 }
 
 pub fn benchmark(c: &mut Criterion<&WgpuTimer>) {
-    let throughput = Throughput::Elements((PROB_M * PROB_N) as u64);
-    wgpu_bencher::benchmark(c, &TIMER, LayerNorm::new(1e-5), throughput)
+    wgpu_bencher::benchmark(c, &TIMER, LayerNorm::new(1e-5));
 }
 
 criterion_group!(
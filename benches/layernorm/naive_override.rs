@@ -0,0 +1,121 @@
+#![allow(non_snake_case)]
+use std::collections::HashMap;
+
+use encase::ShaderType;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use wgpu_bencher::{
+    dispatch_validate, reference, shape, wgs, BenchConfig, CPUTensor, Fixture, GPUHandle,
+    KernelBench, KernelTensor, KernelWork, OpMetadata, TensorRole, WgpuTimer, Workload,
+    LAYERNORM_FLOPS,
+};
+
+lazy_static::lazy_static! {
+    pub static ref TIMER: WgpuTimer = WgpuTimer::new(pollster::block_on(async {
+        GPUHandle::new().await.unwrap()
+    }));
+}
+
+#[derive(ShaderType, derive_new::new, Debug)]
+pub struct LayerNormMeta {
+    M: u32,
+    N: u32,
+    ND4: u32,
+    eps: f32,
+}
+
+impl OpMetadata for LayerNormMeta {}
+
+/// One shader file for every block size, set through the `BLOCK_SIZE` override.
+#[derive(derive_new::new, Debug)]
+pub struct LayerNormOverride {
+    M: usize,
+    N: usize,
+    block_size: usize,
+    eps: f32,
+}
+
+impl KernelBench for LayerNormOverride {
+    type Metadata = LayerNormMeta;
+
+    fn name() -> &'static str {
+        "LayerNormOverride"
+    }
+
+    fn source(&self, _workload: &Workload) -> String {
+        include_str!("../../kernels/layernorm/naive_override.wgsl").to_string()
+    }
+
+    fn constants(&self, _tensors: &[CPUTensor]) -> HashMap<String, f64> {
+        HashMap::from([("BLOCK_SIZE".to_string(), self.block_size as f64)])
+    }
+
+    fn tensors(&self) -> Vec<KernelTensor> {
+        let (M, N) = (self.M, self.N);
+        let input = CPUTensor::randn::<f32>(shape![1, M, N]);
+        let scale = CPUTensor::randn::<f32>(shape![N]);
+        let bias = CPUTensor::randn::<f32>(shape![N]);
+        let output = CPUTensor::zeros::<f32>(shape![1, M, N]);
+        vec![
+            KernelTensor::input(input),
+            KernelTensor::input(scale),
+            KernelTensor::input(bias),
+            KernelTensor::output(output),
+        ]
+    }
+
+    fn workload(&self, tensors: &[CPUTensor]) -> Workload {
+        Workload::builder(tensors[0].shape(), wgs![self.block_size as _, 1, 1])
+            .foldable()
+            .rows()
+    }
+
+    fn metadata(&self, tensors: &[CPUTensor]) -> Self::Metadata {
+        let input = &tensors[0];
+        let [_B, M, N] = input.shape().try_into().unwrap();
+        LayerNormMeta::new(M as _, N as _, (N / 4) as _, self.eps)
+    }
+
+    fn parameter(&self) -> String {
+        self.block_size.to_string()
+    }
+
+    fn work(&self, tensors: &[CPUTensor]) -> KernelWork {
+        KernelWork::per_element(tensors, LAYERNORM_FLOPS)
+    }
+
+    fn fixture_key(&self) -> String {
+        format!("eps{:e}", self.eps)
+    }
+
+    fn validate(&self, handle: &GPUHandle, tensors: &[CPUTensor], roles: &[TensorRole]) {
+        let (input, scale, bias) = (&tensors[0], &tensors[1], &tensors[2]);
+        let ground = Fixture::for_kernel(self, tensors)
+            .outputs_or_else(tensors, || {
+                vec![reference::layernorm(input, scale, bias, self.eps)]
+            })
+            .unwrap()
+            .remove(0);
+        let cpu_result = dispatch_validate(handle, self, tensors, roles).remove(0);
+        ground.all_close(&cpu_result, 1e-5, 1e-5).unwrap();
+    }
+}
+
+pub fn benchmark(c: &mut Criterion<&WgpuTimer>) {
+    let (M, N) = (2048, 2048);
+    let benches = [64, 128, 256]
+        .into_iter()
+        .map(|block_size| LayerNormOverride::new(M, N, block_size, 1e-5));
+    let config = BenchConfig {
+        compile_timings: true,
+        ..Default::default()
+    };
+    wgpu_bencher::benchmark_sweep(c, &TIMER, benches, config);
+}
+
+criterion_group!(
+    name = bench;
+    config = Criterion::default().with_measurement(&*TIMER);
+    targets = benchmark
+);
+criterion_main!(bench);
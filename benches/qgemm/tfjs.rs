@@ -1,14 +1,12 @@
 #![allow(non_snake_case)]
 use encase::ShaderType;
-use inline_python::{python, Context};
-use numpy::PyArrayDyn;
-use pyo3::Python;
-use smallvec::smallvec;
 
-use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use criterion::{criterion_group, criterion_main, Criterion};
 use wgpu_bencher::{
-    dispatch_validate, shape, wgc, wgs, CPUTensor, GPUHandle, KernelBench, KernelContextExt,
-    OpMetadata, Quantization, Quantizer, WgpuTimer, Workload,
+    dispatch_validate, kernel_tera, reference, render_wgsl, shape, wgs, Autotuner, BenchConfig,
+    CPUTensor, Fixture, GPUHandle, KernelBench, KernelContextExt, KernelTensor, KernelWork,
+    OpMetadata, Quantization, Quantizer, TensorRole, Tunable, TuneConfig, TuneParam, WgpuTimer,
+    Workload,
 };
 
 lazy_static::lazy_static! {
@@ -47,9 +45,9 @@ impl QGEMMBenchmark {
         let dimInner = self.K;
 
         let mut shape_fit = [false; 3];
-        shape_fit[0] = aOuter % self.TILE_DIM == 0;
-        shape_fit[1] = bOuter % self.TILE_DIM == 0;
-        shape_fit[2] = dimInner % self.TILE_DIM == 0;
+        shape_fit[0] = aOuter.is_multiple_of(self.TILE_DIM);
+        shape_fit[1] = bOuter.is_multiple_of(self.TILE_DIM);
+        shape_fit[2] = dimInner.is_multiple_of(self.TILE_DIM);
         shape_fit
     }
 }
@@ -62,10 +60,14 @@ impl KernelBench for QGEMMBenchmark {
     }
 
     fn source(&self, workload: &Workload) -> String {
-        let mut tera = tera::Tera::default();
-        let mut context = tera::Context::new();
+        let mut tera = kernel_tera();
         tera.add_raw_template(Self::name(), include_str!("../../kernels/qgemm/tfjs.wgsl"))
             .unwrap();
+        render_wgsl(&tera, Self::name(), &self.context(workload)).unwrap()
+    }
+
+    fn context(&self, workload: &Workload) -> tera::Context {
+        let mut context = tera::Context::new();
         let shape_fit = self.shape_fit();
         context.insert("A_FIT", &shape_fit[0]);
         context.insert("B_FIT", &shape_fit[1]);
@@ -74,26 +76,29 @@ impl KernelBench for QGEMMBenchmark {
         context.insert("TILE_DIM", &self.TILE_DIM);
         context.insert("ROW_PER_THREAD", &self.ROW_PER_THREAD);
         context.insert_workload(workload);
-        tera.render(Self::name(), &context).unwrap()
+        context
     }
 
-    fn tensors(&self) -> Vec<CPUTensor> {
+    fn tensors(&self) -> Vec<KernelTensor> {
         let (B, M, N, K) = (self.B, self.M, self.N, self.K);
         let a = CPUTensor::randn::<f32>(shape![B, M, K]);
         let b_unquant = CPUTensor::randn::<f32>(shape![B, K, N]);
         let quantizer = Quantizer::new(Quantization::SInt8);
         let quantized_b = quantizer.quantize(b_unquant.clone());
         let output = CPUTensor::zeros::<f32>(shape![B, M, N]);
-        vec![a, quantized_b, output]
+        vec![
+            KernelTensor::input(a),
+            KernelTensor::input(quantized_b),
+            KernelTensor::output(output),
+        ]
     }
 
     fn workload(&self, _: &[CPUTensor]) -> Workload {
         let (TILE_DIM, ROW_PER_THREAD) = (self.TILE_DIM, self.ROW_PER_THREAD);
         let workgroup_size = wgs![(TILE_DIM / 4) as _, (TILE_DIM / ROW_PER_THREAD) as _, 1];
-        let group_x = Workload::ceil(self.N, TILE_DIM);
-        let group_y = Workload::ceil(self.M, TILE_DIM);
-        let workgroup_count = wgc![group_x as _, group_y as _, self.B as u32];
-        Workload::new(workgroup_size, workgroup_count)
+        Workload::builder(&shape![self.B, self.M, self.N], workgroup_size)
+            .elements_per_invocation(4, ROW_PER_THREAD)
+            .tiled()
     }
 
     fn metadata(&self, _: &[CPUTensor]) -> Self::Metadata {
@@ -106,41 +111,68 @@ impl KernelBench for QGEMMBenchmark {
         let outShape = glam::IVec3::new(B, M, N);
         let outStrides = glam::IVec3::new(M * N, N, 1);
 
-        let meta = QGEMMMeta::new(aShape, aStrides, bShape, bStrides, outShape, outStrides, K);
-        println!("META: {:?}", meta);
-        meta
+        QGEMMMeta::new(aShape, aStrides, bShape, bStrides, outShape, outStrides, K)
+    }
+
+    fn parameter(&self) -> String {
+        //Sweeps are square, the edge length keeps the parameter numeric
+        self.M.to_string()
+    }
+
+    fn work(&self, tensors: &[CPUTensor]) -> KernelWork {
+        let (B, M, N, K) = (self.B, self.M, self.N, self.K);
+        KernelWork {
+            flops: Some(2 * (B * M * N * K) as u64),
+            ..KernelWork::from_tensors(tensors)
+        }
     }
 
-    fn validate(&self, tensors: &[CPUTensor]) {
+    fn validate(&self, handle: &GPUHandle, tensors: &[CPUTensor], roles: &[TensorRole]) {
         let (a, bquant) = (&tensors[0], &tensors[1]);
-        let dequantized = Quantizer::new(Quantization::SInt8).dequantize(bquant.clone());
-        let ground = Python::with_gil(|py| {
-            let (py_a, py_b) = (a.to_py::<f32>(&py), dequantized.to_py::<f32>(&py));
-            let result: Context = python! {
-                import torch
-                (a, b) = (torch.from_numpy('py_a), torch.from_numpy('py_b))
-                result = (a @ b).numpy()
-            };
-            CPUTensor::from(result.get_with_gil::<&PyArrayDyn<f32>>(py, "result"))
-        });
-        let mut gpu_tensors = dispatch_validate(TIMER.handle(), self);
-        let cpu_result = gpu_tensors.remove(2).into_cpu(TIMER.handle()).unwrap();
-        println!("OURS: {}", cpu_result);
-        println!("GROUND: {}", ground);
+        let ground = Fixture::for_kernel(self, tensors)
+            .outputs_or_else(tensors, || vec![reference::dequant_matmul(a, bquant)])
+            .unwrap()
+            .remove(0);
+        let cpu_result = dispatch_validate(handle, self, tensors, roles).remove(0);
         ground.all_close(&cpu_result, 1e-2, 1e-2).unwrap();
     }
 }
 
+impl Tunable for QGEMMBenchmark {
+    fn tunables(_: &GPUHandle) -> Vec<TuneParam> {
+        vec![
+            TuneParam::new("TILE_DIM", vec![16, 32, 64]),
+            TuneParam::new("ROW_PER_THREAD", vec![2, 4, 8]),
+        ]
+    }
+
+    fn with_config(&self, config: &TuneConfig) -> Self {
+        Self {
+            TILE_DIM: config["TILE_DIM"] as _,
+            ROW_PER_THREAD: config["ROW_PER_THREAD"] as _,
+            ..*self
+        }
+    }
+
+    fn problem(&self) -> String {
+        let (B, M, N, K) = (self.B, self.M, self.N, self.K);
+        format!("{}x{}x{}x{}", B, M, N, K)
+    }
+}
+
 pub fn benchmark(c: &mut Criterion<&WgpuTimer>) {
     let B = 1;
-    let M = 2048;
-    let N = 2048;
-    let K = 2048;
     let TILE_DIM = 32;
     let ROW_PER_THREAD = 8;
-    let bench = QGEMMBenchmark::new(B, M, N, K, TILE_DIM, ROW_PER_THREAD);
-    let throughput = Throughput::Elements(2 * (B * M * N * K) as u64);
-    wgpu_bencher::benchmark(c, &TIMER, bench, throughput)
+    let tuner = Autotuner::new(&TIMER);
+    let benches = [256, 512, 1024, 2048]
+        .into_iter()
+        .map(|dim| {
+            let bench = QGEMMBenchmark::new(B, dim, dim, dim, TILE_DIM, ROW_PER_THREAD);
+            tuner.tune(&bench).unwrap()
+        })
+        .collect::<Vec<_>>();
+    wgpu_bencher::benchmark_sweep(c, &TIMER, benches, BenchConfig::default());
 }
 
 criterion_group!(
@@ -1,14 +1,11 @@
 #![allow(non_snake_case)]
 use encase::ShaderType;
-use inline_python::{python, Context};
-use numpy::PyArrayDyn;
-use pyo3::{IntoPy, Python};
-use smallvec::smallvec;
 
-use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use criterion::{criterion_group, criterion_main, Criterion};
 use wgpu_bencher::{
-    dispatch_validate, shape, wgc, wgs, CPUTensor, GPUHandle, KernelBench, KernelContextExt,
-    OpMetadata, WgpuTimer, Workload,
+    dispatch_validate, kernel_tera, reference, render_wgsl, shape, wgs, Autotuner, BenchConfig,
+    CPUTensor, Fixture, GPUHandle, KernelBench, KernelContextExt, KernelTensor, KernelWork,
+    OpMetadata, TensorRole, Tunable, TuneConfig, TuneParam, WgpuTimer, Workload,
 };
 
 lazy_static::lazy_static! {
@@ -32,6 +29,7 @@ pub struct SGEMMMeta {
 
 impl OpMetadata for SGEMMMeta {}
 
+#[allow(clippy::too_many_arguments)]
 #[derive(derive_new::new, Debug)]
 pub struct SGEMMBenchmark {
     B: usize,
@@ -54,7 +52,6 @@ impl SGEMMBenchmark {
         shape_fit[0] = aOuter % self.TILE_DIM == 0;
         shape_fit[1] = bOuter % self.TILE_DIM == 0;
         shape_fit[2] = dimInner % self.TILE_DIM == 0;
-        println!("SHAPE FIT: {:?}", shape_fit);
         shape_fit
     }
 }
@@ -67,16 +64,20 @@ impl KernelBench for SGEMMBenchmark {
     }
 
     fn source(&self, workload: &Workload) -> String {
-        let mut tera = tera::Tera::default();
-        let mut context = tera::Context::new();
-
-        let is_vec4 = (self.M % 4 == 0) && (self.N % 4 == 0) && (self.K % 4 == 0);
+        let mut tera = kernel_tera();
+        let is_vec4 =
+            self.M.is_multiple_of(4) && self.N.is_multiple_of(4) && self.K.is_multiple_of(4);
         let template = if is_vec4 {
             include_str!("../../kernels/sgemm/tfjs.wgsl")
         } else {
             include_str!("../../kernels/sgemm/scalar_tf.wgsl")
         };
         tera.add_raw_template(Self::name(), template).unwrap();
+        render_wgsl(&tera, Self::name(), &self.context(workload)).unwrap()
+    }
+
+    fn context(&self, workload: &Workload) -> tera::Context {
+        let mut context = tera::Context::new();
         let shape_fit = self.shape_fit();
         context.insert("FIT_A_OUTER", &shape_fit[0]);
         context.insert("FIT_B_OUTER", &shape_fit[1]);
@@ -87,28 +88,27 @@ impl KernelBench for SGEMMBenchmark {
         context.insert("TILE_DIM", &self.TILE_DIM);
         context.insert("ROW_PER_THREAD", &self.ROW_PER_THREAD);
         context.insert_workload(workload);
-        let kernel = tera.render(Self::name(), &context).unwrap();
-        println!("{}", kernel);
-        kernel
+        context
     }
 
-    fn tensors(&self) -> Vec<CPUTensor> {
+    fn tensors(&self) -> Vec<KernelTensor> {
         let (B, M, N, K) = (self.B, self.M, self.N, self.K);
         let a = CPUTensor::randn::<f32>(shape![B, M, K]);
         let b = CPUTensor::randn::<f32>(shape![B, K, N]);
         let output = CPUTensor::zeros::<f32>(shape![B, M, N]);
-        vec![a, b, output]
+        vec![
+            KernelTensor::input(a),
+            KernelTensor::input(b),
+            KernelTensor::output(output),
+        ]
     }
 
     fn workload(&self, _: &[CPUTensor]) -> Workload {
         let (TILE_DIM, ROW_PER_THREAD) = (self.TILE_DIM, self.ROW_PER_THREAD);
         let workgroup_size = wgs![(TILE_DIM / 4) as _, (TILE_DIM / ROW_PER_THREAD) as _, 1];
-        let group_x = Workload::ceil(self.N, TILE_DIM);
-        let group_y = Workload::ceil(self.M, TILE_DIM);
-        let workgroup_count = wgc![group_x as _, group_y as _, self.B as u32];
-        let dispatch = Workload::new(workgroup_size, workgroup_count);
-        println!("DISPATCH: {:?}", dispatch);
-        dispatch
+        Workload::builder(&shape![self.B, self.M, self.N], workgroup_size)
+            .elements_per_invocation(4, ROW_PER_THREAD)
+            .tiled()
     }
 
     fn metadata(&self, _: &[CPUTensor]) -> Self::Metadata {
@@ -125,7 +125,7 @@ impl KernelBench for SGEMMBenchmark {
         let dimBOuter = if self.trans_b { K } else { N };
         let dimInner = if self.trans_a { M } else { K };
 
-        let meta = SGEMMMeta {
+        SGEMMMeta {
             aShape,
             aStrides,
             bShape,
@@ -135,52 +135,83 @@ impl KernelBench for SGEMMBenchmark {
             dimAOuter,
             dimBOuter,
             dimInner,
-        };
-        println!("META: {:?}", meta);
-        meta
+        }
+    }
+
+    fn parameter(&self) -> String {
+        //Sweeps are square, the edge length keeps the parameter numeric
+        self.M.to_string()
+    }
+
+    fn work(&self, tensors: &[CPUTensor]) -> KernelWork {
+        let (B, M, N, K) = (self.B, self.M, self.N, self.K);
+        KernelWork {
+            flops: Some(2 * (B * M * N * K) as u64),
+            ..KernelWork::from_tensors(tensors)
+        }
     }
 
-    fn validate(&self, tensors: &[CPUTensor]) {
+    //Transposed variants share shapes
+    fn fixture_key(&self) -> String {
+        let layout = |trans: bool| if trans { "T" } else { "N" };
+        format!("{}{}", layout(self.trans_a), layout(self.trans_b))
+    }
+
+    fn validate(&self, handle: &GPUHandle, tensors: &[CPUTensor], roles: &[TensorRole]) {
         let (a, b) = (&tensors[0], &tensors[1]);
         let (trans_a, trans_b) = (self.trans_a, self.trans_b);
-        let ground = Python::with_gil(|py| {
-            let (py_a, py_b) = (a.to_py::<f32>(&py), b.to_py::<f32>(&py));
-            let (py_trans_a, py_trans_b) = (trans_a.into_py(py), trans_b.into_py(py));
-            let result: Context = python! {
-                import torch
-                (a, b) = (torch.from_numpy('py_a), torch.from_numpy('py_b))
-                if 'py_trans_a:
-                    print("Transposing A in Python")
-                    a = torch.permute(a, (0, 2, 1))
-                if 'py_trans_b:
-                    print("Transposing B in Python")
-                    b = torch.permute(b, (0, 2, 1))
-                result = (a @ b).numpy()
-            };
-            CPUTensor::from(result.get_with_gil::<&PyArrayDyn<f32>>(py, "result"))
-        });
-        let mut gpu_tensors = dispatch_validate(TIMER.handle(), self);
-        let cpu_result = gpu_tensors.remove(2).into_cpu(TIMER.handle()).unwrap();
-        println!("GROUND: {}", ground);
-        println!("OURS: {}", cpu_result);
+        let ground = Fixture::for_kernel(self, tensors)
+            .outputs_or_else(tensors, || vec![reference::matmul(a, b, trans_a, trans_b)])
+            .unwrap()
+            .remove(0);
+        let cpu_result = dispatch_validate(handle, self, tensors, roles).remove(0);
         ground.all_close(&cpu_result, 1e-5, 1e-5).unwrap();
     }
 }
 
+impl Tunable for SGEMMBenchmark {
+    fn tunables(_: &GPUHandle) -> Vec<TuneParam> {
+        vec![
+            TuneParam::new("TILE_DIM", vec![16, 32, 64]),
+            TuneParam::new("ROW_PER_THREAD", vec![2, 4, 8]),
+        ]
+    }
+
+    fn with_config(&self, config: &TuneConfig) -> Self {
+        Self {
+            TILE_DIM: config["TILE_DIM"] as _,
+            ROW_PER_THREAD: config["ROW_PER_THREAD"] as _,
+            ..*self
+        }
+    }
+
+    fn problem(&self) -> String {
+        let (B, M, N, K) = (self.B, self.M, self.N, self.K);
+        format!(
+            "{}x{}x{}x{} trans_a={} trans_b={}",
+            B, M, N, K, self.trans_a, self.trans_b
+        )
+    }
+}
+
 pub fn benchmark(c: &mut Criterion<&WgpuTimer>) {
     let B = 1;
-    let M = 2048;
-    let N = 2048;
-    let K = 2048;
     let TILE_DIM = 32;
     let ROW_PER_THREAD = 8;
 
     let trans_a = false;
     let trans_b = false;
 
-    let bench = SGEMMBenchmark::new(B, M, N, K, TILE_DIM, ROW_PER_THREAD, trans_a, trans_b);
-    let throughput = Throughput::Elements(2 * (B * M * N * K) as u64);
-    wgpu_bencher::benchmark(c, &TIMER, bench, throughput)
+    let tuner = Autotuner::new(&TIMER);
+    let benches = [256, 512, 1024, 2048]
+        .into_iter()
+        .map(|dim| {
+            let bench =
+                SGEMMBenchmark::new(B, dim, dim, dim, TILE_DIM, ROW_PER_THREAD, trans_a, trans_b);
+            tuner.tune(&bench).unwrap()
+        })
+        .collect::<Vec<_>>();
+    wgpu_bencher::benchmark_sweep(c, &TIMER, benches, BenchConfig::default());
 }
 
 criterion_group!(
@@ -0,0 +1,230 @@
+#![allow(non_snake_case)]
+use encase::ShaderType;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use wgpu_bencher::{
+    dispatch_validate, kernel_tera, reference, render_wgsl, shape, wgc, wgs, BenchConfig,
+    CPUTensor, DispatchMode, Fixture, GPUHandle, KernelBench, KernelTensor, KernelWork, OpMetadata,
+    Stage, TensorRole, WgpuTimer, Workload, SOFTMAX_FLOPS,
+};
+
+lazy_static::lazy_static! {
+    pub static ref TIMER: WgpuTimer = WgpuTimer::new(pollster::block_on(async {
+        GPUHandle::new().await.unwrap()
+    }));
+}
+
+#[derive(ShaderType, derive_new::new, Debug)]
+pub struct SoftmaxMeta {
+    M: u32,
+    N: u32,
+}
+
+impl OpMetadata for SoftmaxMeta {}
+
+/// Two pass softmax over the first `rows` of an `M`x`N` input, the row count living in a
+/// GPU buffer as it would with dynamic sequence lengths. A first stage writes the dispatch
+/// args from it, both softmax passes are then dispatched indirectly.
+#[derive(derive_new::new, Debug)]
+pub struct SoftmaxIndirect {
+    M: usize,
+    N: usize,
+    rows: usize,
+}
+
+impl KernelBench for SoftmaxIndirect {
+    type Metadata = SoftmaxMeta;
+
+    fn name() -> &'static str {
+        "SoftmaxIndirect"
+    }
+
+    fn source(&self, workload: &Workload) -> String {
+        let mut tera = kernel_tera();
+        tera.add_raw_template(
+            Self::name(),
+            include_str!("../../kernels/softmax/two_pass.wgsl"),
+        )
+        .unwrap();
+        render_wgsl(&tera, Self::name(), &self.context(workload)).unwrap()
+    }
+
+    fn tensors(&self) -> Vec<KernelTensor> {
+        let (M, N) = (self.M, self.N);
+        let input = CPUTensor::randn::<f32>(shape![M, N]);
+        let stats = CPUTensor::zeros::<f32>(shape![M, 2]);
+        let output = CPUTensor::zeros::<f32>(shape![M, N]);
+        let lengths = CPUTensor::from_slice(&[self.rows as u32], shape![1]);
+        let args = CPUTensor::zeros::<u32>(shape![3]);
+        vec![
+            KernelTensor::input(input),
+            KernelTensor::intermediate(stats),
+            KernelTensor::output(output),
+            KernelTensor::input(lengths),
+            KernelTensor::intermediate(args),
+        ]
+    }
+
+    //Sized for all M rows, the dispatch only covers the valid ones
+    fn workload(&self, tensors: &[CPUTensor]) -> Workload {
+        Workload::builder(tensors[0].shape(), wgs![128, 1, 1]).rows()
+    }
+
+    fn stages(&self, tensors: &[CPUTensor]) -> Vec<Stage> {
+        let workload = self.workload(tensors);
+        let source = self.source(&workload);
+        let context = self.context(&workload);
+        vec![
+            Stage::new(
+                "dispatch_args".to_string(),
+                include_str!("../../kernels/softmax/dispatch_args.wgsl").to_string(),
+                "main".to_string(),
+                Workload::new(wgs![1, 1, 1], wgc![1, 1, 1]),
+                vec![3, 4],
+            ),
+            Stage::new(
+                "row_stats".to_string(),
+                source.clone(),
+                "row_stats".to_string(),
+                workload.clone(),
+                vec![0, 1],
+            )
+            .with_context(context.clone())
+            .with_indirect(4, 0),
+            Stage::new(
+                "row_normalize".to_string(),
+                source,
+                "row_normalize".to_string(),
+                workload,
+                vec![0, 1, 2],
+            )
+            .with_context(context)
+            .with_indirect(4, 0),
+        ]
+    }
+
+    fn metadata(&self, tensors: &[CPUTensor]) -> Self::Metadata {
+        let [M, N] = tensors[0].shape().try_into().unwrap();
+        SoftmaxMeta::new(M as _, N as _)
+    }
+
+    fn parameter(&self) -> String {
+        self.rows.to_string()
+    }
+
+    //Only the valid rows are read and written
+    fn work(&self, _: &[CPUTensor]) -> KernelWork {
+        let elements = (self.rows * self.N) as u64;
+        KernelWork {
+            bytes: Some(2 * elements * std::mem::size_of::<f32>() as u64),
+            flops: Some(elements * SOFTMAX_FLOPS),
+        }
+    }
+
+    //The row count only shows in the contents of `lengths`, not in the shapes
+    fn fixture_key(&self) -> String {
+        format!("rows{}", self.rows)
+    }
+
+    fn validate(&self, handle: &GPUHandle, tensors: &[CPUTensor], roles: &[TensorRole]) {
+        let input = &tensors[0];
+        let ground = Fixture::for_kernel(self, tensors)
+            .outputs_or_else(tensors, || {
+                //Rows past the valid ones are never dispatched, so stay zeroed
+                let mut probs = reference::softmax(input).to_vec::<f32>().unwrap();
+                probs[self.rows * self.N..].fill(0.);
+                vec![CPUTensor::from_slice(&probs, shape![self.M, self.N])]
+            })
+            .unwrap()
+            .remove(0);
+        let cpu_result = dispatch_validate(handle, self, tensors, roles).remove(0);
+        ground.all_close(&cpu_result, 1e-6, 1e-4).unwrap();
+    }
+}
+
+/// The same passes over every row, their counts known on the host. Timed with
+/// `DispatchMode::Indirect` next to a direct dispatch, this isolates what indirection costs.
+#[derive(Debug)]
+pub struct SoftmaxHostCount(SoftmaxIndirect);
+
+impl SoftmaxHostCount {
+    pub fn new(M: usize, N: usize) -> Self {
+        Self(SoftmaxIndirect::new(M, N, M))
+    }
+}
+
+impl KernelBench for SoftmaxHostCount {
+    type Metadata = SoftmaxMeta;
+
+    fn name() -> &'static str {
+        "SoftmaxHostCount"
+    }
+
+    fn source(&self, workload: &Workload) -> String {
+        self.0.source(workload)
+    }
+
+    //Only the input, stats and output, the row count and args are never read
+    fn tensors(&self) -> Vec<KernelTensor> {
+        self.0.tensors().into_iter().take(3).collect()
+    }
+
+    fn workload(&self, tensors: &[CPUTensor]) -> Workload {
+        self.0.workload(tensors)
+    }
+
+    //Without the stage writing the args, nor reading them
+    fn stages(&self, tensors: &[CPUTensor]) -> Vec<Stage> {
+        self.0
+            .stages(tensors)
+            .into_iter()
+            .skip(1)
+            .map(|stage| Stage {
+                indirect: None,
+                ..stage
+            })
+            .collect()
+    }
+
+    fn metadata(&self, tensors: &[CPUTensor]) -> Self::Metadata {
+        self.0.metadata(tensors)
+    }
+
+    fn parameter(&self) -> String {
+        self.0.parameter()
+    }
+
+    fn work(&self, tensors: &[CPUTensor]) -> KernelWork {
+        self.0.work(tensors)
+    }
+
+    fn validate(&self, handle: &GPUHandle, tensors: &[CPUTensor], roles: &[TensorRole]) {
+        let ground = Fixture::for_kernel(self, tensors)
+            .outputs_or_else(tensors, || vec![reference::softmax(&tensors[0])])
+            .unwrap()
+            .remove(0);
+        let cpu_result = dispatch_validate(handle, self, tensors, roles).remove(0);
+        ground.all_close(&cpu_result, 1e-6, 1e-4).unwrap();
+    }
+}
+
+pub fn benchmark(c: &mut Criterion<&WgpuTimer>) {
+    let (M, N) = (2048, 1024);
+    let benches = [256, 1024, 2048].map(|rows| SoftmaxIndirect::new(M, N, rows));
+    wgpu_bencher::benchmark_sweep(c, &TIMER, benches, BenchConfig::default());
+}
+
+pub fn indirection_cost(c: &mut Criterion<&WgpuTimer>) {
+    let config = BenchConfig {
+        dispatch: DispatchMode::Indirect,
+        ..Default::default()
+    };
+    wgpu_bencher::benchmark_with_config(c, &TIMER, SoftmaxHostCount::new(2048, 1024), config);
+}
+
+criterion_group!(
+    name = bench;
+    config = Criterion::default().with_measurement(&*TIMER);
+    targets = benchmark, indirection_cost
+);
+criterion_main!(bench);
@@ -0,0 +1,124 @@
+#![allow(non_snake_case)]
+use encase::ShaderType;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use wgpu_bencher::{
+    dispatch_validate, kernel_tera, reference, render_wgsl, shape, wgs, BenchConfig, CPUTensor,
+    Fixture, GPUHandle, KernelBench, KernelTensor, KernelWork, OpMetadata, Stage, TensorRole,
+    WgpuTimer, Workload, SOFTMAX_FLOPS,
+};
+
+lazy_static::lazy_static! {
+    pub static ref TIMER: WgpuTimer = WgpuTimer::new(pollster::block_on(async {
+        GPUHandle::new().await.unwrap()
+    }));
+}
+
+#[derive(ShaderType, derive_new::new, Debug)]
+pub struct SoftmaxMeta {
+    M: u32,
+    N: u32,
+}
+
+impl OpMetadata for SoftmaxMeta {}
+
+#[derive(derive_new::new, Debug)]
+pub struct SoftmaxTwoPass {
+    M: usize,
+    N: usize,
+}
+
+impl KernelBench for SoftmaxTwoPass {
+    type Metadata = SoftmaxMeta;
+
+    fn name() -> &'static str {
+        "SoftmaxTwoPass"
+    }
+
+    fn source(&self, workload: &Workload) -> String {
+        let mut tera = kernel_tera();
+        tera.add_raw_template(
+            Self::name(),
+            include_str!("../../kernels/softmax/two_pass.wgsl"),
+        )
+        .unwrap();
+        render_wgsl(&tera, Self::name(), &self.context(workload)).unwrap()
+    }
+
+    fn tensors(&self) -> Vec<KernelTensor> {
+        let (M, N) = (self.M, self.N);
+        let input = CPUTensor::randn::<f32>(shape![M, N]);
+        let stats = CPUTensor::zeros::<f32>(shape![M, 2]);
+        let output = CPUTensor::zeros::<f32>(shape![M, N]);
+        vec![
+            KernelTensor::input(input),
+            KernelTensor::intermediate(stats),
+            KernelTensor::output(output),
+        ]
+    }
+
+    fn workload(&self, tensors: &[CPUTensor]) -> Workload {
+        Workload::builder(tensors[0].shape(), wgs![128, 1, 1])
+            .foldable()
+            .rows()
+    }
+
+    //Both passes live in one module, one workgroup per row
+    fn stages(&self, tensors: &[CPUTensor]) -> Vec<Stage> {
+        let workload = self.workload(tensors);
+        let source = self.source(&workload);
+        let context = self.context(&workload);
+        vec![
+            Stage::new(
+                "row_stats".to_string(),
+                source.clone(),
+                "row_stats".to_string(),
+                workload.clone(),
+                vec![0, 1],
+            )
+            .with_context(context.clone()),
+            Stage::new(
+                "row_normalize".to_string(),
+                source,
+                "row_normalize".to_string(),
+                workload,
+                vec![0, 1, 2],
+            )
+            .with_context(context),
+        ]
+    }
+
+    fn metadata(&self, tensors: &[CPUTensor]) -> Self::Metadata {
+        let [M, N] = tensors[0].shape().try_into().unwrap();
+        SoftmaxMeta::new(M as _, N as _)
+    }
+
+    fn work(&self, tensors: &[CPUTensor]) -> KernelWork {
+        KernelWork::per_element(tensors, SOFTMAX_FLOPS)
+    }
+
+    fn validate(&self, handle: &GPUHandle, tensors: &[CPUTensor], roles: &[TensorRole]) {
+        let input = &tensors[0];
+        let ground = Fixture::for_kernel(self, tensors)
+            .outputs_or_else(tensors, || vec![reference::softmax(input)])
+            .unwrap()
+            .remove(0);
+        let cpu_result = dispatch_validate(handle, self, tensors, roles).remove(0);
+        ground.all_close(&cpu_result, 1e-6, 1e-4).unwrap();
+    }
+}
+
+pub fn benchmark(c: &mut Criterion<&WgpuTimer>) {
+    let config = BenchConfig {
+        stage_timings: true,
+        ..Default::default()
+    };
+    wgpu_bencher::benchmark_with_config(c, &TIMER, SoftmaxTwoPass::new(2048, 1024), config);
+}
+
+criterion_group!(
+    name = bench;
+    config = Criterion::default().with_measurement(&*TIMER);
+    targets = benchmark
+);
+criterion_main!(bench);
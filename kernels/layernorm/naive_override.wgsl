@@ -0,0 +1,84 @@
+//Plain WGSL, its override set as a pipeline constant rather than rendered by tera
+override BLOCK_SIZE: u32 = 128u;
+
+@group(0) @binding(0)
+var<storage, read> X: array<f32>;
+
+@group(0) @binding(1)
+var<storage, read> S: array<f32>;
+
+@group(0) @binding(2)
+var<storage, read> B: array<f32>;
+
+@group(0) @binding(3)
+var<storage, read_write> Y: array<f32>;
+
+struct Meta {
+    M: u32,
+    N: u32,
+    ND4: u32,
+    eps: f32,
+}
+
+@group(1) @binding(0)
+var<uniform> metadata: Meta;
+
+var<workgroup> smem: array<f32, BLOCK_SIZE>;
+
+fn block_sum(local_id: vec3<u32>, value: f32) -> f32 {
+    smem[local_id.x] = value;
+    workgroupBarrier();
+    for (var stride = BLOCK_SIZE / 2u; stride > 0u; stride >>= 1u) {
+        if local_id.x < stride {
+            smem[local_id.x] += smem[local_id.x + stride];
+        }
+        workgroupBarrier();
+    }
+    let sum = smem[0];
+    workgroupBarrier();
+    return sum;
+}
+
+fn mu(local_id: vec3<u32>, anchor: u32) -> f32 {
+    var threadSum = 0f;
+    for (var i: u32 = local_id.x; i < metadata.N; i += BLOCK_SIZE) {
+        threadSum += X[anchor + i];
+    }
+    return block_sum(local_id, threadSum) / f32(metadata.N);
+}
+
+fn sigma(local_id: vec3<u32>, anchor: u32, mu: f32) -> f32 {
+    var threadSum = 0f;
+    for (var i: u32 = local_id.x; i < metadata.N; i += BLOCK_SIZE) {
+        let val = X[anchor + i] - mu;
+        threadSum += (val * val);
+    }
+    return block_sum(local_id, threadSum) / f32(metadata.N);
+}
+
+//Rows beyond 65535 are folded into y and z, see `Workload::fold`
+fn linear_workgroup_id(group_id: vec3<u32>, num_groups: vec3<u32>) -> u32 {
+    return group_id.x + group_id.y * num_groups.x + group_id.z * num_groups.x * num_groups.y;
+}
+
+@compute @workgroup_size(BLOCK_SIZE, 1, 1)
+fn main( 
+        @builtin(local_invocation_id) local_id: vec3<u32>,
+        @builtin(workgroup_id) group_id: vec3<u32>,
+        @builtin(num_workgroups) num_groups: vec3<u32>
+) {
+    let row = linear_workgroup_id(group_id, num_groups);
+    if (row >= metadata.M) {
+        return;
+    }
+    let anchor = row * metadata.N;
+    let mu = mu(local_id, anchor);
+    let sigma = sigma(local_id, anchor, mu);
+
+    let denom = inverseSqrt(sigma + metadata.eps);
+
+    for(var i: u32 = local_id.x; i < metadata.N; i += BLOCK_SIZE) {
+        let core = (X[anchor + i] - mu) * denom;
+        Y[anchor + i] = fma(core, S[i], B[i]); 
+    }
+}
@@ -24,12 +24,7 @@ const BLOCK_SIZE: u32 = 128u;
 
 var<workgroup> smem: array<vec4<f32>, BLOCK_SIZE>; //max 16kb
 
-fn block_sum(index: u32, stride: u32) {
-    if index < stride {
-        smem[index] += smem[index + stride];
-    }
-    workgroupBarrier();
-}
+{% include "lib/reduce.wgsl" %}
 
 fn mu(local_id: vec3<u32>, anchor: u32) -> f32 {
     var threadSum = vec4<f32>(0.0);
@@ -71,13 +66,20 @@ fn sigma(local_id: vec3<u32>, anchor: u32, mu: f32) -> f32 {
     return dot(smem[0], vec4<f32>(1.0)) / (f32(metadata.N));
 }
 
+{% include "lib/workgroup.wgsl" %}
+
 @compute @workgroup_size({{ workgroup_size_x }}, {{ workgroup_size_y }}, {{ workgroup_size_z }})
 fn main( 
         @builtin(local_invocation_id) local_id: vec3<u32>,
         @builtin(workgroup_id) group_id: vec3<u32>,
+        @builtin(num_workgroups) num_groups: vec3<u32>,
         @builtin(global_invocation_id) global_id: vec3<u32>
 ) {
-    let anchor = (group_id.y * metadata.M * metadata.ND4) + group_id.x * metadata.ND4; 
+    let row = linear_workgroup_id(group_id, num_groups);
+    if (row >= metadata.M) {
+        return;
+    }
+    let anchor = row * metadata.ND4;
     let mu = mu(local_id, anchor);
     let sigma = sigma(local_id, anchor, mu);
 
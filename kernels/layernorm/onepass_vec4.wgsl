@@ -35,13 +35,20 @@ fn block_reduce(index: u32, stride: u32) {
     workgroupBarrier();
 }
 
+{% include "lib/workgroup.wgsl" %}
+
 @compute @workgroup_size(128, 1, 1)
 fn main(
 @builtin(global_invocation_id) global_id: vec3<u32>,
 @builtin(local_invocation_id) local_id: vec3<u32>,
-@builtin(workgroup_id) group_id: vec3<u32>
+@builtin(workgroup_id) group_id: vec3<u32>,
+@builtin(num_workgroups) num_groups: vec3<u32>
 ) {
-    let anchor = (group_id.y * metadata.M * metadata.ND4) + group_id.x * metadata.ND4; 
+    let row = linear_workgroup_id(group_id, num_groups);
+    if (row >= metadata.M) {
+        return;
+    }
+    let anchor = row * metadata.ND4;
 
     for (var i = local_id.x; i < metadata.ND4; i += BLOCK_SIZE) {
        let val = X[anchor + i];
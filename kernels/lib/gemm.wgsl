@@ -0,0 +1,46 @@
+{#- Index math and accessors for the tfjs style GEMMs.
+    Expects `metadata` with `aStrides`, `bStrides` and the output strides, and the buffers A, B and result. -#}
+
+{% macro indexing(out_strides="outStrides") %}
+fn getAIndexFromCoords3D(coords : vec3<i32>) -> i32 {
+    return dot(coords, metadata.aStrides);
+}
+
+fn getBIndexFromCoords3D(coords : vec3<i32>) -> i32 {
+    return dot(coords, metadata.bStrides);
+}
+
+fn getOutputIndexFromCoords(coords : vec3<i32>) -> i32 {
+    return dot(coords, metadata.{{ out_strides }});
+}
+{% endmacro indexing %}
+
+{#- `width` elements per array entry, e.g 4 for `vec4<f32>` -#}
+{% macro output(elem="vec4<f32>", width=4) %}
+fn setOutputAtIndex(flatIndex : i32, value : {{ elem }}) {
+    result[flatIndex] = {{ elem }}(value);
+}
+
+fn setOutputAtCoords(d0 : i32, d1 : i32, d2 : i32, value : {{ elem }}) {
+    let flatIndex = getOutputIndexFromCoords(vec3<i32>(d0, d1, d2));
+    setOutputAtIndex(flatIndex{% if width > 1 %} / {{ width }}{% endif %}, value);
+}
+{% endmacro output %}
+
+{% macro load(operand, elem="vec4<f32>", width=4) %}
+fn get{{ operand }}(d0 : i32, d1 : i32, d2 : i32) -> {{ elem }} {
+    return {{ elem }}({{ operand }}[get{{ operand }}IndexFromCoords3D(vec3<i32>(d0, d1, d2)){% if width > 1 %} / {{ width }}{% endif %}]);
+}
+{% endmacro load %}
+
+{#- SInt8 weights: 4 signed bytes packed per u32, scaled by one absmax per `group_size` values -#}
+{% macro load_sint8(operand="B", absmax="absmax", group_size=16) %}
+fn get{{ operand }}(d0 : i32, d1 : i32, d2 : i32) -> vec4<f32> {
+    return unpack4x8snorm({{ operand }}[get{{ operand }}IndexFromCoords3D(vec3<i32>(d0, d1, d2)) / 4]);
+}
+
+fn getAbsMax(d0 : i32, d1 : i32, d2 : i32) -> f32 {
+    let abs_index = get{{ operand }}IndexFromCoords3D(vec3<i32>(d0, d1, d2)) / {{ group_size }};
+    return {{ absmax }}[abs_index];
+}
+{% endmacro load_sint8 %}
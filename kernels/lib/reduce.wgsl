@@ -0,0 +1,15 @@
+//Tree reductions over the workgroup array `smem`, halve `stride` each step.
+//Expects `smem` to be declared by the including kernel.
+fn block_sum(index: u32, stride: u32) {
+    if index < stride {
+        smem[index] += smem[index + stride];
+    }
+    workgroupBarrier();
+}
+
+fn block_max(index: u32, stride: u32) {
+    if index < stride {
+        smem[index] = max(smem[index], smem[index + stride]);
+    }
+    workgroupBarrier();
+}
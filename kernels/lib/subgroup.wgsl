@@ -0,0 +1,23 @@
+//Subgroup reductions of Welford statistics, include after lib/welford.wgsl.
+//The kernel stores the subgroup size in `subgrp_size` before reducing.
+var<workgroup> subgrp_size: u32;
+
+fn welford_warp_reduce(thread_mean: f32, thread_m2: f32, thread_count: f32, mean: ptr<function, f32>, m2: ptr<function, f32>, count: ptr<function, f32>) {
+    *mean = thread_mean;
+    *m2 = thread_m2;
+    *count = thread_count;
+    for (var offset = subgrp_size >> 1u; offset > 0u; offset >>= 1u) {
+        let b_mean = subgroupShuffleDown(*mean, offset);
+        let b_m2 = subgroupShuffleDown(*m2, offset);
+        let b_count = subgroupShuffleDown(*count, offset);
+        block_welford_combine(b_mean, b_m2, b_count, mean, m2, count);
+    }
+}
+
+fn welford_warp_all_reduce(thread_mean: f32, thread_m2: f32, thread_count: f32, mean: ptr<function, f32>, m2: ptr<function, f32>, count: ptr<function, f32>) {
+    welford_warp_reduce(thread_mean, thread_m2, thread_count, mean, m2, count);
+
+    *mean = subgroupBroadcast(*mean, 0u);
+    *m2 = subgroupBroadcast(*m2, 0u);
+    *count = subgroupBroadcast(*count, 0u);
+}
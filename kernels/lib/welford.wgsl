@@ -0,0 +1,29 @@
+//Welford's online mean and variance, m2 is the sum of squared deviations.
+fn welford_combine(val: f32, mean: ptr<function, f32>, m2: ptr<function, f32>, count: ptr<function, f32>) {
+    *count += 1.0;
+    let delta1 = val - *mean;
+    *mean += delta1 / *count;
+    let delta2 = val - *mean;
+    *m2 += delta1 * delta2;
+}
+
+fn welford_vcombine(val: vec4<f32>, mean: ptr<function, vec4<f32>>, m2: ptr<function, vec4<f32>>, count: ptr<function, vec4<f32>>) { 
+    *count += 1.0;
+    let delta1 = val - *mean;
+    *mean += delta1 / *count;
+    let delta2 = val - *mean;
+    *m2 += delta1 * delta2;
+}
+
+//Merges the partial statistics of another block
+fn block_welford_combine(b_mean: f32, b_m2: f32, b_count: f32, mean: ptr<function, f32>, m2: ptr<function, f32>, count: ptr<function, f32>) {
+    if (b_count == 0.0) {
+        return;
+    }
+    let new_count = *count + b_count; 
+    let nb_over_n = b_count / new_count;
+    let delta = b_mean - *mean;
+    *mean += delta * nb_over_n;
+    *m2 += b_m2 + delta * delta * (*count) * nb_over_n;
+    *count = new_count;
+}
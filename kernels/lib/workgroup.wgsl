@@ -0,0 +1,5 @@
+//Linear index of this workgroup, for 1D counts `Workload` folded into 2D or 3D.
+//Indices at or past the requested count are padding, `insert_workload` provides it as `workgroups`.
+fn linear_workgroup_id(group_id: vec3<u32>, num_groups: vec3<u32>) -> u32 {
+    return group_id.x + group_id.y * num_groups.x + group_id.z * num_groups.x * num_groups.y;
+}
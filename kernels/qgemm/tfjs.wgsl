@@ -1,37 +1,9 @@
-fn getAIndexFromCoords3D(coords : vec3<i32>) -> i32 {
-    return dot(coords, metadata.aStrides);
-}
-
-fn getBIndexFromCoords3D(coords : vec3<i32>) -> i32 {
-    return dot(coords, metadata.bStrides);
-}
-
-fn getOutputIndexFromCoords(coords : vec3<i32>) -> i32 {
-  return dot(coords, metadata.outShapeStrides);
-}
-        
-fn setOutputAtIndex(flatIndex : i32, value : vec4<f32>) {
-    result[flatIndex] = vec4<f32>(value);
-}
+{% import "lib/gemm.wgsl" as gemm %}
+{{ gemm::indexing(out_strides="outShapeStrides") }}
+{{ gemm::output(elem="vec4<f32>", width=4) }}
+{{ gemm::load(operand="A", elem="vec4<f32>", width=4) }}
+{{ gemm::load_sint8(operand="B", absmax="absmax", group_size=16) }}
 
-fn setOutputAtCoords(d0 : i32, d1 : i32, d2 : i32, value : vec4<f32>) {
-    let flatIndex = getOutputIndexFromCoords(vec3<i32>(d0, d1, d2));
-    setOutputAtIndex(flatIndex / 4, value);
-}
-
-fn getA(d0 : i32, d1 : i32, d2 : i32) -> vec4<f32> {
-    return vec4<f32>(A[getAIndexFromCoords3D(vec3<i32>(d0,d1,d2)) / 4]);
-}
-   
-fn getB(d0 : i32, d1 : i32, d2 : i32) -> vec4<f32> {
-    return unpack4x8snorm(B[getBIndexFromCoords3D(vec3<i32>(d0,d1,d2)) / 4]);
-}
-
-fn getAbsMax(d0 : i32, d1 : i32, d2 : i32) -> f32 {
-    let abs_index = getBIndexFromCoords3D(vec3<i32>(d0,d1,d2)) / 16;
-    return absmax[abs_index]; 
-}
-   
 {% if A_FIT %}
 fn mm_readA(batch: i32, row: i32, col: i32) -> vec4<f32> {
     var value = vec4<f32>(0.0);
@@ -0,0 +1,20 @@
+@group(0) @binding(0)
+var<storage, read> X: array<vec4<f32>>;
+
+@group(0) @binding(1)
+var<storage, read_write> Y: array<vec4<f32>>;
+
+struct Meta {
+    numel: u32, //vec4s
+}
+
+@group(1) @binding(0)
+var<uniform> metadata: Meta;
+
+@compute @workgroup_size({{ workgroup_size_x }}, {{ workgroup_size_y }}, {{ workgroup_size_z }})
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index < metadata.numel) {
+        Y[index] = X[index];
+    }
+}
@@ -0,0 +1,28 @@
+@group(0) @binding(0)
+var<storage, read_write> Y: array<vec4<f32>>;
+
+struct Meta {
+    iterations: u32,
+}
+
+@group(1) @binding(0)
+var<uniform> metadata: Meta;
+
+//4 independent vec4 FMA chains per invocation, 32 FLOPs per iteration
+@compute @workgroup_size({{ workgroup_size_x }}, {{ workgroup_size_y }}, {{ workgroup_size_z }})
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let seed = f32(global_id.x);
+    var a = vec4<f32>(seed, seed + 1.0, seed + 2.0, seed + 3.0);
+    var b = a + 0.5;
+    var c = a + 1.5;
+    var d = a + 2.5;
+    let m = vec4<f32>(0.999);
+    let k = vec4<f32>(0.001);
+    for (var i = 0u; i < metadata.iterations; i++) {
+        a = fma(a, m, k);
+        b = fma(b, m, k);
+        c = fma(c, m, k);
+        d = fma(d, m, k);
+    }
+    Y[global_id.x] = a + b + c + d;
+}
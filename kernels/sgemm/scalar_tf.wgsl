@@ -1,32 +1,9 @@
-fn getAIndexFromCoords3D(coords : vec3<i32>) -> i32 {
-    return dot(coords, metadata.aStrides);
-}
-
-fn getBIndexFromCoords3D(coords : vec3<i32>) -> i32 {
-    return dot(coords, metadata.bStrides);
-}
-
-fn getOutputIndexFromCoords(coords : vec3<i32>) -> i32 {
-  return dot(coords, metadata.outShapeStrides);
-}
-        
-fn setOutputAtIndex(flatIndex: i32, value: f32) {
-    result[flatIndex] = f32(value);
-}
-
-fn setOutputAtCoords(d0: i32, d1: i32, d2: i32, value: f32) {
-    let flatIndex = getOutputIndexFromCoords(vec3<i32>(d0, d1, d2));
-    setOutputAtIndex(flatIndex, value);
-}
+{% import "lib/gemm.wgsl" as gemm %}
+{{ gemm::indexing(out_strides="outShapeStrides") }}
+{{ gemm::output(elem="f32", width=1) }}
+{{ gemm::load(operand="A", elem="f32", width=1) }}
+{{ gemm::load(operand="B", elem="f32", width=1) }}
 
-fn getA(d0: i32, d1: i32, d2: i32) -> f32 {
-    return f32(A[getAIndexFromCoords3D(vec3<i32>(d0, d1, d2))]);
-}
-   
-fn getB(d0: i32, d1: i32, d2: i32) -> f32 {
-    return f32(B[getBIndexFromCoords3D(vec3<i32>(d0, d1, d2))]);
-}
-   
 {% if FIT_A_OUTER and FIT_INNER %}
 fn mm_readA(batch: i32, row: i32, col: i32) -> f32 {
     var value = f32(0.0);
@@ -0,0 +1,21 @@
+@group(0) @binding(0)
+var<storage, read> lengths: array<u32>; //Valid rows, as computed on the GPU by a runtime
+
+@group(0) @binding(1)
+var<storage, read_write> args: array<u32>; //Workgroup counts x, y, z
+
+struct Meta {
+    M: u32,
+    N: u32,
+}
+
+@group(1) @binding(0)
+var<uniform> metadata: Meta;
+
+//One workgroup per valid row, M stays within a single dimension
+@compute @workgroup_size(1, 1, 1)
+fn main() {
+    args[0] = min(lengths[0], metadata.M);
+    args[1] = 1u;
+    args[2] = 1u;
+}
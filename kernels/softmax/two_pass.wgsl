@@ -0,0 +1,95 @@
+@group(0) @binding(0)
+var<storage, read> X: array<f32>;
+
+@group(0) @binding(1)
+var<storage, read_write> stats: array<f32>; //(max, sum of exp) per row
+
+@group(0) @binding(2)
+var<storage, read_write> Y: array<f32>;
+
+struct Meta {
+    M: u32,
+    N: u32,
+}
+
+@group(1) @binding(0)
+var<uniform> metadata: Meta;
+
+const BLOCK_SIZE: u32 = 128u;
+
+var<workgroup> smem: array<f32, BLOCK_SIZE>;
+
+{% include "lib/reduce.wgsl" %}
+{% include "lib/workgroup.wgsl" %}
+
+//Pass 1: row max and the sum of exponentials shifted by it
+@compute @workgroup_size({{ workgroup_size_x }}, {{ workgroup_size_y }}, {{ workgroup_size_z }})
+fn row_stats( 
+        @builtin(local_invocation_id) local_id: vec3<u32>,
+        @builtin(workgroup_id) group_id: vec3<u32>,
+        @builtin(num_workgroups) num_groups: vec3<u32>
+) {
+    let row = linear_workgroup_id(group_id, num_groups);
+    if (row >= metadata.M) {
+        return;
+    }
+    let anchor = row * metadata.N;
+
+    var threadMax = -3.402823e+38f;
+    for (var i: u32 = local_id.x; i < metadata.N; i += BLOCK_SIZE) {
+        threadMax = max(threadMax, X[anchor + i]);
+    }
+    smem[local_id.x] = threadMax;
+    workgroupBarrier();
+
+    block_max(local_id.x, 64u);
+    block_max(local_id.x, 32u);
+    block_max(local_id.x, 16u);
+    block_max(local_id.x, 8u);
+    block_max(local_id.x, 4u);
+    block_max(local_id.x, 2u);
+    block_max(local_id.x, 1u);
+
+    let rowMax = smem[0];
+    workgroupBarrier();
+
+    var threadSum = 0f;
+    for (var i: u32 = local_id.x; i < metadata.N; i += BLOCK_SIZE) {
+        threadSum += exp(X[anchor + i] - rowMax);
+    }
+    smem[local_id.x] = threadSum;
+    workgroupBarrier();
+
+    block_sum(local_id.x, 64u);
+    block_sum(local_id.x, 32u);
+    block_sum(local_id.x, 16u);
+    block_sum(local_id.x, 8u);
+    block_sum(local_id.x, 4u);
+    block_sum(local_id.x, 2u);
+    block_sum(local_id.x, 1u);
+
+    if local_id.x == 0u {
+        stats[2u * row] = rowMax;
+        stats[2u * row + 1u] = smem[0];
+    }
+}
+
+//Pass 2: normalize with the stats of pass 1
+@compute @workgroup_size({{ workgroup_size_x }}, {{ workgroup_size_y }}, {{ workgroup_size_z }})
+fn row_normalize( 
+        @builtin(local_invocation_id) local_id: vec3<u32>,
+        @builtin(workgroup_id) group_id: vec3<u32>,
+        @builtin(num_workgroups) num_groups: vec3<u32>
+) {
+    let row = linear_workgroup_id(group_id, num_groups);
+    if (row >= metadata.M) {
+        return;
+    }
+    let anchor = row * metadata.N;
+    let rowMax = stats[2u * row];
+    let denom = 1f / stats[2u * row + 1u];
+
+    for (var i: u32 = local_id.x; i < metadata.N; i += BLOCK_SIZE) {
+        Y[anchor + i] = exp(X[anchor + i] - rowMax) * denom;
+    }
+}
@@ -1,8 +1,18 @@
-use std::borrow::Cow;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
-use criterion::{BenchmarkId, Criterion, Throughput};
+use criterion::{BenchmarkGroup, BenchmarkId, Criterion};
+use wgpu::util::DeviceExt;
 
-use crate::{CPUTensor, GPUBuffer, GPUHandle, GPUTensor, OpMetadata, WgpuTimer, Workload};
+use crate::{
+    comparison_table, segment_count, summary_table, validate_entry_point, BenchSummary,
+    BindingLayout, CPUTensor, CacheFlush, DeviceProfile, DispatchStats, GPUBuffer, GPUHandle,
+    GPUTensor, KernelTensor, KernelThroughput, KernelWork, OpMetadata, Roofline, ShaderDump, Stage,
+    TensorRole, TimingSource, WgpuTimer, Workload, MAX_QUERY_PAIRS,
+};
 
 pub trait KernelContextExt {
     fn insert_workload(&mut self, workload: &Workload);
@@ -13,6 +23,173 @@ impl KernelContextExt for tera::Context {
         self.insert("workgroup_size_x", &workload.size().0);
         self.insert("workgroup_size_y", &workload.size().1);
         self.insert("workgroup_size_z", &workload.size().2);
+        let (x, y, z) = workload.count().as_tuple();
+        self.insert("workgroup_count_x", &x);
+        self.insert("workgroup_count_y", &y);
+        self.insert("workgroup_count_z", &z);
+        self.insert("workgroups", &workload.requested().total());
+        self.insert("folded", &workload.is_folded());
+    }
+}
+
+/// Timed iterations per stage when `BenchConfig::stage_timings` is set.
+const STAGE_ITERATIONS: usize = 100;
+
+/// How timestamps are placed around the dispatches of a timed iteration.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimingMode {
+    /// All dispatches are encoded in one compute pass, which is timed as a whole.
+    #[default]
+    Pass,
+    /// Every dispatch is timed on its own, with timestamps inside the pass if the
+    /// device supports them, otherwise with one compute pass per dispatch.
+    PerDispatch,
+}
+
+/// State of the GPU caches when a timed pass starts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CacheMode {
+    /// Buffers stay resident from the previous dispatches.
+    #[default]
+    Warm,
+    /// A scratch copy of `BenchConfig::cache_flush_bytes` evicts the caches before every timed pass,
+    /// outside the timestamps.
+    /// Only the first dispatch of a pass runs cold, so pair with `TimingMode::PerDispatch`
+    /// or a single dispatch per pass. `benchmark` reports the warm numbers alongside.
+    Cold,
+}
+
+/// How stages whose counts are known on the host are dispatched.
+/// Stages reading `Stage::indirect` args are always dispatched indirectly.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DispatchMode {
+    #[default]
+    Direct,
+    /// `dispatch_workgroups_indirect` from a buffer holding the workload's count, to measure
+    /// what indirection costs. `benchmark` reports the direct numbers alongside.
+    Indirect,
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    /// Number of dispatches encoded per timed pass.
+    pub dispatches_per_pass: u64,
+    /// Number of timed passes encoded into one command buffer, one submission per iteration
+    /// unless their query pairs overflow the query set, see `timed_submissions`.
+    /// Above 1, submission and `poll(Wait)` are amortised over several passes.
+    /// Ignored with `CacheMode::Cold`, which needs a flush before every pass.
+    pub passes_per_submit: u64,
+    pub timing: TimingMode,
+    pub cache: CacheMode,
+    /// Size of each `CacheFlush` scratch buffer for `CacheMode::Cold`.
+    /// Must exceed the device's last level cache for the flush to evict everything.
+    pub cache_flush_bytes: u64,
+    pub dispatch: DispatchMode,
+    /// Device ceilings for the roofline report, falls back to `WGPU_BENCH_PROFILE`.
+    pub profile: Option<DeviceProfile>,
+    /// Also time every stage of a multi-stage kernel on its own, after the whole sequence.
+    pub stage_timings: bool,
+    /// Also print how long each stage's shader module and pipeline took to create, e.g. to weigh
+    /// `override` constants, which only need a new pipeline, against re-rendering templates.
+    pub compile_timings: bool,
+    /// Writes every stage's WGSL, context and naga translations under this directory,
+    /// in a subdirectory per `label` and parameter. Falls back to `WGPU_BENCH_DUMP`.
+    pub dump_dir: Option<PathBuf>,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            dispatches_per_pass: WgpuTimer::COMPUTE_PER_QUERY,
+            passes_per_submit: 1,
+            timing: TimingMode::default(),
+            cache: CacheMode::default(),
+            cache_flush_bytes: CacheFlush::SCRATCH_BYTES,
+            dispatch: DispatchMode::default(),
+            profile: None,
+            stage_timings: false,
+            compile_timings: false,
+            dump_dir: None,
+        }
+    }
+}
+
+impl BenchConfig {
+    /// Rejects settings that leave nothing to time, which would otherwise divide by zero
+    /// when per-dispatch times are derived.
+    pub fn check(&self) -> anyhow::Result<()> {
+        if self.dispatches_per_pass == 0 {
+            anyhow::bail!("BenchConfig::dispatches_per_pass must be at least 1");
+        }
+        if self.passes_per_submit == 0 {
+            anyhow::bail!("BenchConfig::passes_per_submit must be at least 1");
+        }
+        Ok(())
+    }
+
+    /// Number of dispatches covered by a single timestamp pair.
+    pub fn dispatches_per_query(&self) -> u64 {
+        match self.timing {
+            TimingMode::Pass => self.dispatches_per_pass,
+            TimingMode::PerDispatch => 1,
+        }
+    }
+
+    /// Kernel name, suffixed with whatever sets this run apart from the default.
+    pub fn label(&self, name: &str) -> String {
+        let label = match self.cache {
+            CacheMode::Cold => format!("{} (cold)", name),
+            CacheMode::Warm if self.passes_per_submit > 1 => {
+                format!("{} (x{} per submit)", name, self.passes_per_submit)
+            }
+            CacheMode::Warm => name.to_string(),
+        };
+        match self.dispatch {
+            DispatchMode::Direct => label,
+            DispatchMode::Indirect => format!("{} (indirect)", label),
+        }
+    }
+
+    /// Number of dispatches timed by one criterion iteration.
+    pub fn dispatches_per_iter(&self) -> u64 {
+        self.dispatches_per_pass * self.passes_per_submit
+    }
+
+    /// Dispatches in each timed pass of one iteration. A `PerDispatch` pass with more dispatches
+    /// than the query set has pairs is split, each dispatch is timed on its own regardless.
+    pub fn timed_passes(&self) -> Vec<u64> {
+        let max_dispatches = MAX_QUERY_PAIRS as u64 * self.dispatches_per_query();
+        let mut passes = vec![];
+        for _ in 0..self.passes_per_submit {
+            let mut remaining = self.dispatches_per_pass;
+            while remaining > 0 {
+                let dispatches = remaining.min(max_dispatches);
+                passes.push(dispatches);
+                remaining -= dispatches;
+            }
+        }
+        passes
+    }
+
+    /// `timed_passes` grouped into submissions, each writing no more query pairs than the
+    /// query set holds, so batching `passes_per_submit` passes never overflows the ring.
+    pub fn timed_submissions(&self) -> Vec<Vec<u64>> {
+        let mut submissions: Vec<Vec<u64>> = vec![];
+        let mut pairs = 0;
+        for dispatches in self.timed_passes() {
+            let queries = dispatches / self.dispatches_per_query();
+            match submissions.last_mut() {
+                Some(passes) if pairs + queries <= MAX_QUERY_PAIRS as u64 => {
+                    passes.push(dispatches);
+                    pairs += queries;
+                }
+                _ => {
+                    submissions.push(vec![dispatches]);
+                    pairs = queries;
+                }
+            }
+        }
+        submissions
     }
 }
 
@@ -20,37 +197,189 @@ pub trait KernelBench: std::fmt::Debug {
     type Metadata: OpMetadata;
     fn name() -> &'static str;
     fn source(&self, workload: &Workload) -> String;
-    fn tensors(&self) -> Vec<CPUTensor>;
+    /// Values `source` renders its template with, written out by `BenchConfig::dump_dir`.
+    /// Defaults to the workgroup size.
+    fn context(&self, workload: &Workload) -> tera::Context {
+        let mut context = tera::Context::new();
+        context.insert_workload(workload);
+        context
+    }
+    /// Tensors in binding order, tagged with how the kernel uses them.
+    fn tensors(&self) -> Vec<KernelTensor>;
     fn workload(&self, tensors: &[CPUTensor]) -> Workload;
     fn metadata(&self, tensors: &[CPUTensor]) -> Self::Metadata;
-    fn validate(&self, tensors: &[CPUTensor]);
+    /// Checks the kernel's outputs on `tensors`, dispatching it on `handle`: the device being timed.
+    fn validate(&self, handle: &GPUHandle, tensors: &[CPUTensor], roles: &[TensorRole]);
+
+    /// Settings the tensor shapes don't capture but the reference outputs depend on, such as an
+    /// epsilon or transposes, keying `Fixture::for_kernel`. Defaults to none.
+    fn fixture_key(&self) -> String {
+        String::new()
+    }
+
+    /// Identifies this configuration within a sweep, such as its problem shape.
+    /// Criterion only draws line charts when every parameter in a group is numeric.
+    fn parameter(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    /// Pipelines run in order as one dispatch of the kernel, sharing its tensors.
+    /// Defaults to a single stage running `main` from `source`, with every tensor bound.
+    fn stages(&self, tensors: &[CPUTensor]) -> Vec<Stage> {
+        let workload = self.workload(tensors);
+        let source = self.source(&workload);
+        let context = self.context(&workload);
+        let bindings = (0..tensors.len()).collect();
+        let stage = Stage::new(
+            Self::name().to_string(),
+            source,
+            "main".to_string(),
+            workload,
+            bindings,
+        );
+        vec![stage
+            .with_layout(self.binding_layout(tensors))
+            .with_context(context)
+            .with_constants(self.constants(tensors))]
+    }
+
+    /// Values for `override` declarations in `source`, keyed by `@id` where declared and by name otherwise, passed as pipeline constants.
+    /// One source can then serve a whole sweep. Defaults to none, leaving every override at its default.
+    fn constants(&self, _tensors: &[CPUTensor]) -> HashMap<String, f64> {
+        HashMap::new()
+    }
+
+    /// Uniform buffers, in the order of `BindingLayout::uniforms`. Defaults to just the metadata.
+    fn uniforms(&self, handle: &GPUHandle, tensors: &[CPUTensor]) -> Vec<GPUBuffer> {
+        vec![self.metadata(tensors).into_buffer(handle)]
+    }
+
+    /// Where every tensor segment and uniform is bound, for single stage kernels.
+    /// Defaults to `BindingLayout::packed`: storage 4 per group in tensor order,
+    /// then a group holding the uniforms.
+    fn binding_layout(&self, tensors: &[CPUTensor]) -> BindingLayout {
+        BindingLayout::packed(segment_count(tensors), 1)
+    }
+
+    /// Bytes moved and FLOPs performed by one dispatch.
+    /// Defaults to touching every tensor once, override to declare FLOPs.
+    fn work(&self, tensors: &[CPUTensor]) -> KernelWork {
+        KernelWork::from_tensors(tensors)
+    }
 }
 
-pub fn dispatch_validate<K: KernelBench>(handle: &GPUHandle, kernel: &K) -> Vec<GPUTensor> {
+/// Dispatches the kernel once on `tensors`, as handed to `validate`,
+/// returning its `Output` and `InOut` tensors read back in order.
+pub fn dispatch_validate<K: KernelBench>(
+    handle: &GPUHandle,
+    kernel: &K,
+    tensors: &[CPUTensor],
+    roles: &[TensorRole],
+) -> Vec<CPUTensor> {
     let _ = env_logger::builder().is_test(true).try_init();
-    let tensors = kernel.tensors();
-    let workload = kernel.workload(&tensors);
-    log::debug!("Workload: {:?}", workload);
-    let source = kernel.source(&workload);
-    log::debug!("Source: {}", source);
-    let pipeline = source_to_pipeline(handle, &source);
-    let uniform_buffer = kernel.metadata(&tensors).into_buffer(handle);
-    let gpu_tensors = tensors
-        .into_iter()
-        .map(|t| t.into_gpu(handle))
-        .collect::<Vec<_>>();
-    let bind_groups = tensors_to_bind_groups(handle, &gpu_tensors, uniform_buffer, &pipeline);
-    dispatch(handle, &workload, &bind_groups, &pipeline, None);
+    let (stages, gpu_tensors) = prepare(
+        handle,
+        kernel,
+        tensors.to_vec(),
+        roles,
+        DispatchMode::Direct,
+    );
+    dispatch(handle, &stages, None, DispatchMode::Direct);
     gpu_tensors
+        .into_iter()
+        .zip(roles)
+        .filter(|(_, role)| role.is_written())
+        .map(|(t, _)| t.into_cpu(handle).unwrap())
+        .collect()
 }
 
+/// A stage compiled and bound to the kernel's uploaded tensors, ready for dispatch.
+#[derive(Debug)]
+pub struct PreparedStage {
+    pub label: String,
+    pub workload: Workload,
+    pub pipeline: wgpu::ComputePipeline,
+    pub bind_groups: Vec<wgpu::BindGroup>,
+    /// Time taken to create the shader module, next to nothing if an earlier stage shared its source.
+    pub module_time: Duration,
+    /// Time taken to create the pipeline, applying the stage's pipeline constants.
+    pub pipeline_time: Duration,
+    /// Buffer and offset of the counts written on the GPU, see `Stage::indirect`.
+    pub indirect: Option<(GPUBuffer, u64)>,
+    /// The workload's count as indirect args, only for stages prepared for `DispatchMode::Indirect`.
+    pub host_args: Option<GPUBuffer>,
+}
+
+impl PreparedStage {
+    #[inline(always)]
+    fn encode_dispatch<'a>(&'a self, cpass: &mut wgpu::ComputePass<'a>, mode: DispatchMode) {
+        match (&self.indirect, mode) {
+            (Some((buffer, offset)), _) => cpass.dispatch_workgroups_indirect(buffer, *offset),
+            (None, DispatchMode::Indirect) => {
+                let args = self
+                    .host_args
+                    .as_ref()
+                    .unwrap_or_else(|| panic!("Stage {} prepared for direct dispatch", self.label));
+                cpass.dispatch_workgroups_indirect(args, 0)
+            }
+            (None, DispatchMode::Direct) => {
+                let (x, y, z) = self.workload.count().as_tuple();
+                cpass.dispatch_workgroups(x, y, z);
+            }
+        }
+    }
+}
+
+#[inline(always)]
+fn bind_stage<'a>(cpass: &mut wgpu::ComputePass<'a>, stage: &'a PreparedStage) {
+    for (i, bind_group) in stage.bind_groups.iter().enumerate() {
+        cpass.set_bind_group(i as _, bind_group, &[]);
+    }
+    cpass.set_pipeline(&stage.pipeline);
+}
+
+/// Dispatches every stage once, in order, binding each unless `bound`.
+#[inline(always)]
+fn encode_sequence<'a>(
+    cpass: &mut wgpu::ComputePass<'a>,
+    stages: &'a [PreparedStage],
+    bound: bool,
+    mode: DispatchMode,
+) {
+    for stage in stages {
+        if !bound {
+            bind_stage(cpass, stage);
+        }
+        stage.encode_dispatch(cpass, mode);
+    }
+}
+
+/// Encodes `dispatches` runs of the stage sequence.
+/// A lone stage stays bound across dispatches, returning whether it was bound.
+#[inline(always)]
+fn encode_dispatches<'a>(
+    cpass: &mut wgpu::ComputePass<'a>,
+    stages: &'a [PreparedStage],
+    dispatches: u64,
+    mode: DispatchMode,
+) -> bool {
+    let bound = stages.len() == 1;
+    if bound {
+        bind_stage(cpass, &stages[0]);
+    }
+    for _ in 0..dispatches {
+        encode_sequence(cpass, stages, bound, mode);
+    }
+    bound
+}
+
+/// Dispatches the kernel once, optionally timing the pass.
 #[inline(always)]
 pub fn dispatch(
     handle: &GPUHandle,
-    workload: &Workload,
-    bind_groups: &[wgpu::BindGroup],
-    pipeline: &wgpu::ComputePipeline,
+    stages: &[PreparedStage],
     timestamp_writes: Option<wgpu::ComputePassTimestampWrites>,
+    mode: DispatchMode,
 ) {
     let mut encoder = handle
         .device()
@@ -60,53 +389,222 @@ pub fn dispatch(
             label: None,
             timestamp_writes,
         });
-        for (i, bind_group) in bind_groups.iter().enumerate() {
-            cpass.set_bind_group(i as _, bind_group, &[]);
-        }
-        cpass.set_pipeline(pipeline);
-        let (x, y, z) = workload.count().as_tuple();
-        for _ in 0..WgpuTimer::COMPUTE_PER_QUERY {
-            cpass.dispatch_workgroups(x, y, z);
-        }
+        encode_dispatches(&mut cpass, stages, 1, mode);
     }
     handle.queue().submit(Some(encoder.finish()));
     handle.device().poll(wgpu::Maintain::Wait);
 }
 
-pub fn source_to_pipeline(handle: &GPUHandle, source: &str) -> wgpu::ComputePipeline {
-    let shader_module = unsafe {
-        handle
+/// Splits the iteration into submissions of `passes_per_submit` timed passes,
+/// or of a single pass preceded by a cache flush when running cold.
+#[inline(always)]
+fn dispatch_per_submit(timer: &WgpuTimer, stages: &[PreparedStage], config: &BenchConfig) {
+    let handle = timer.handle();
+    let timestamps = timer.source() == TimingSource::Timestamps;
+    let per_pass = config.dispatches_per_query();
+    let passes = match config.cache {
+        CacheMode::Warm => config.passes_per_submit,
+        CacheMode::Cold => 1,
+    };
+    for _ in 0..config.dispatches_per_iter() / (per_pass * passes) {
+        if config.cache == CacheMode::Cold {
+            timer.flush_cache(config.cache_flush_bytes);
+        }
+        if timestamps {
+            timer
+                .reserve(passes as _)
+                .unwrap_or_else(|e| panic!("{}", e));
+        }
+        let mut encoder = handle
             .device()
-            .create_shader_module_unchecked(wgpu::ShaderModuleDescriptor {
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        for _ in 0..passes {
+            {
+                let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: None,
+                    timestamp_writes: timestamps.then(|| timer.timestamp_writes()),
+                });
+                encode_dispatches(&mut cpass, stages, per_pass, config.dispatch);
+            }
+            if timestamps {
+                timer.increment_query();
+            }
+        }
+        timer.submit(encoder.finish(), per_pass * passes);
+    }
+}
+
+/// Encodes and submits one timed criterion iteration, as described by `config`.
+/// A dispatch runs every stage in order, and is timed as a whole.
+#[inline(always)]
+pub fn dispatch_timed(timer: &WgpuTimer, stages: &[PreparedStage], config: &BenchConfig) {
+    //Host timing covers whole submissions, and a flush in the same submission
+    //could overlap with the kernel, so both split the iteration into several submissions
+    if timer.source() == TimingSource::HostClock || config.cache == CacheMode::Cold {
+        return dispatch_per_submit(timer, stages, config);
+    }
+    let handle = timer.handle();
+    let inside_pass = handle
+        .device()
+        .features()
+        .contains(wgpu::Features::TIMESTAMP_QUERY_INSIDE_PASSES);
+
+    //Every query of a submission must fit in the ring, it cannot wrap mid-encoder
+    for passes in config.timed_submissions() {
+        let queries = passes
+            .iter()
+            .map(|dispatches| dispatches / config.dispatches_per_query())
+            .sum::<u64>();
+        timer
+            .reserve(queries as _)
+            .unwrap_or_else(|e| panic!("{}", e));
+        let mut encoder = handle
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        for &dispatches in &passes {
+            encode_timed_pass(timer, &mut encoder, stages, config, dispatches, inside_pass);
+        }
+        timer.submit(encoder.finish(), passes.iter().sum());
+    }
+}
+
+/// Encodes one timed pass of `dispatches` into `encoder`, room for its queries must already be reserved.
+#[inline(always)]
+fn encode_timed_pass(
+    timer: &WgpuTimer,
+    encoder: &mut wgpu::CommandEncoder,
+    stages: &[PreparedStage],
+    config: &BenchConfig,
+    dispatches: u64,
+    inside_pass: bool,
+) {
+    match config.timing {
+        TimingMode::Pass => {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
                 label: None,
-                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(source)),
-            })
-    };
+                timestamp_writes: Some(timer.timestamp_writes()),
+            });
+            encode_dispatches(&mut cpass, stages, dispatches, config.dispatch);
+            timer.increment_query();
+        }
+        TimingMode::PerDispatch if inside_pass => {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            let bound = encode_dispatches(&mut cpass, stages, 0, config.dispatch); //Bind only
+            for _ in 0..dispatches {
+                let query = timer.current_query();
+                cpass.write_timestamp(timer.query_set(), query.start);
+                encode_sequence(&mut cpass, stages, bound, config.dispatch);
+                cpass.write_timestamp(timer.query_set(), query.end);
+                timer.increment_query();
+            }
+        }
+        TimingMode::PerDispatch => {
+            for _ in 0..dispatches {
+                let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: None,
+                    timestamp_writes: Some(timer.timestamp_writes()),
+                });
+                encode_dispatches(&mut cpass, stages, 1, config.dispatch);
+                timer.increment_query();
+            }
+        }
+    }
+}
+
+pub fn source_to_pipeline(handle: &GPUHandle, source: &str) -> wgpu::ComputePipeline {
+    source_to_pipeline_with_entry(handle, source, "main")
+}
+
+pub fn source_to_pipeline_with_entry(
+    handle: &GPUHandle,
+    source: &str,
+    entry_point: &str,
+) -> wgpu::ComputePipeline {
+    //naga reports readable errors, the driver might not
+    if let Err(e) = validate_entry_point(source, entry_point) {
+        panic!("{}", e);
+    }
+    let module = handle.shader_module(source);
+    module_to_pipeline(handle, &module, entry_point, &HashMap::new())
+}
 
+/// Creates a pipeline from `module`, `constants` setting its `override` declarations.
+pub fn module_to_pipeline(
+    handle: &GPUHandle,
+    module: &wgpu::ShaderModule,
+    entry_point: &str,
+    constants: &HashMap<String, f64>,
+) -> wgpu::ComputePipeline {
     handle
         .device()
         .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
             label: None,
             layout: None,
-            module: &shader_module,
-            entry_point: "main",
+            module,
+            entry_point: Some(entry_point),
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants,
+                ..Default::default()
+            },
+            cache: None,
         })
 }
 
+/// Compiles a stage, its `constants` passed as pipeline constants.
+/// Stages sharing a source share its shader module.
+pub fn stage_to_pipeline(handle: &GPUHandle, stage: &Stage) -> wgpu::ComputePipeline {
+    if let Err(e) = validate_entry_point(&stage.source, &stage.entry_point) {
+        panic!("{}", e);
+    }
+    if let Err(e) = stage.specialized_module() {
+        panic!("{}", e);
+    }
+    let module = handle.shader_module(&stage.source);
+    module_to_pipeline(handle, &module, &stage.entry_point, &stage.constants)
+}
+
+/// Binds the tensors and the uniform as `BindingLayout::packed` lays them out.
 pub fn tensors_to_bind_groups(
     handle: &GPUHandle,
     tensors: &[GPUTensor],
     uniform_buffer: GPUBuffer,
     pipeline: &wgpu::ComputePipeline,
 ) -> Vec<wgpu::BindGroup> {
-    let mut bind_group_entries = vec![];
+    let segments = tensors.iter().map(|t| t.bindings().len()).sum();
+    let layout = BindingLayout::packed(segments, 1);
+    tensors_to_bind_groups_with_layout(handle, tensors, &[uniform_buffer], &layout, pipeline)
+}
 
-    for tensor in tensors {
-        bind_group_entries.append(&mut tensor.bindings(bind_group_entries.len()));
+/// One bind group per group index of the layout, empty for any group it skips.
+pub fn tensors_to_bind_groups_with_layout(
+    handle: &GPUHandle,
+    tensors: &[GPUTensor],
+    uniforms: &[GPUBuffer],
+    layout: &BindingLayout,
+    pipeline: &wgpu::ComputePipeline,
+) -> Vec<wgpu::BindGroup> {
+    let storage = tensors.iter().flat_map(|t| t.bindings());
+    let uniform = uniforms.iter().map(|u| u.as_entire_binding());
+    let mut groups = (0..layout.group_count())
+        .map(|_| vec![])
+        .collect::<Vec<_>>();
+    for (slot, resource) in layout
+        .storage
+        .iter()
+        .zip(storage)
+        .chain(layout.uniforms.iter().zip(uniform))
+    {
+        groups[slot.group as usize].push(wgpu::BindGroupEntry {
+            binding: slot.binding,
+            resource,
+        });
     }
 
-    let mut standard_bind_groups = bind_group_entries
-        .chunks(4)
+    groups
+        .iter()
         .enumerate()
         .map(|(i, entries)| {
             handle
@@ -117,50 +615,496 @@ pub fn tensors_to_bind_groups(
                     entries,
                 })
         })
+        .collect()
+}
+
+/// Compiles every stage and uploads the kernel's tensors, ready for dispatch.
+/// Panics if a stage exceeds the device limits, its layout doesn't fit its tensors,
+/// the roles disagree with the access modes it declares, or its indirect args don't fit.
+/// Under `DispatchMode::Indirect`, stages without GPU-written args get their count uploaded as args.
+pub(crate) fn prepare<K: KernelBench>(
+    handle: &GPUHandle,
+    kernel: &K,
+    tensors: Vec<CPUTensor>,
+    roles: &[TensorRole],
+    mode: DispatchMode,
+) -> (Vec<PreparedStage>, Vec<GPUTensor>) {
+    let limits = handle.device().limits();
+    //Folded against the device's limit rather than the portable one the kernel built with
+    let stages = kernel
+        .stages(&tensors)
+        .into_iter()
+        .map(|stage| Stage {
+            workload: stage
+                .workload
+                .folded_to(limits.max_compute_workgroups_per_dimension),
+            ..stage
+        })
+        .collect::<Vec<_>>();
+    let uniforms = kernel.uniforms(handle, &tensors);
+    let layouts = stages
+        .iter()
+        .map(|stage| {
+            log::debug!("Stage {}: {:?}", stage.label, stage.workload);
+            if let Some(coverage) = stage.workload.coverage() {
+                log::debug!("Coverage: {}", coverage);
+            }
+            log::debug!("Source: {}", stage.source);
+            validate_entry_point(&stage.source, &stage.entry_point)
+                .unwrap_or_else(|e| panic!("{}: {}", K::name(), e));
+            stage
+                .check_limits(&tensors, &limits)
+                .unwrap_or_else(|e| panic!("{}: {}", K::name(), e));
+            stage
+                .check_indirect(&tensors)
+                .unwrap_or_else(|e| panic!("{}: {}", K::name(), e));
+            stage
+                .checked_layout(&tensors, roles, uniforms.len())
+                .unwrap_or_else(|e| panic!("{}: {}", K::name(), e))
+        })
         .collect::<Vec<_>>();
 
-    let uniform_bind_group = handle
-        .device()
-        .create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &pipeline.get_bind_group_layout(standard_bind_groups.len() as _),
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: uniform_buffer.as_entire_binding(),
-            }],
-        });
-    standard_bind_groups.push(uniform_bind_group);
-    standard_bind_groups
+    //Tensors holding indirect args are also read by the dispatch itself
+    let indirect = stages
+        .iter()
+        .filter_map(|s| s.indirect.map(|args| args.tensor))
+        .collect::<Vec<_>>();
+    let gpu_tensors = tensors
+        .into_iter()
+        .zip(roles)
+        .enumerate()
+        .map(|(i, (t, role))| {
+            let mut usage = role.usage();
+            if indirect.contains(&i) {
+                usage |= wgpu::BufferUsages::INDIRECT;
+            }
+            t.into_gpu_with_usage(handle, usage)
+        })
+        .collect::<Vec<_>>();
+    let prepared = stages
+        .into_iter()
+        .zip(layouts)
+        .map(|(stage, layout)| {
+            //naga checked the stage above, only wgpu's work is timed
+            let start = Instant::now();
+            let module = handle.shader_module(&stage.source);
+            let module_time = start.elapsed();
+            let start = Instant::now();
+            let pipeline =
+                module_to_pipeline(handle, &module, &stage.entry_point, &stage.constants);
+            let pipeline_time = start.elapsed();
+            let bind_groups = tensors_to_bind_groups_with_layout(
+                handle,
+                &stage.bound(&gpu_tensors),
+                &uniforms,
+                &layout,
+                &pipeline,
+            );
+            let indirect = stage.indirect.map(|args| {
+                let buffer = gpu_tensors[args.tensor].storage().inner().clone();
+                (buffer, args.offset)
+            });
+            let host_args = (mode == DispatchMode::Indirect && indirect.is_none()).then(|| {
+                let (x, y, z) = stage.workload.count().as_tuple();
+                handle
+                    .device()
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: None,
+                        contents: bytemuck::cast_slice(&[x, y, z]),
+                        usage: wgpu::BufferUsages::INDIRECT,
+                    })
+                    .into()
+            });
+            PreparedStage {
+                label: stage.label,
+                workload: stage.workload,
+                pipeline,
+                bind_groups,
+                module_time,
+                pipeline_time,
+                indirect,
+                host_args,
+            }
+        })
+        .collect();
+    (prepared, gpu_tensors)
+}
+
+/// Times `iterations` passes of the kernel outside of criterion, without validating it.
+pub fn measure<K: KernelBench>(
+    timer: &WgpuTimer,
+    kernel: &K,
+    config: &BenchConfig,
+    iterations: usize,
+) -> anyhow::Result<DispatchStats> {
+    config.check()?;
+    let (tensors, roles) = KernelTensor::split(kernel.tensors());
+    let (stages, _gpu_tensors) = prepare(timer.handle(), kernel, tensors, &roles, config.dispatch);
+    time_stages(timer, &stages, config, iterations)?
+        .ok_or_else(|| anyhow::anyhow!("No samples recorded for {}", K::name()))
+}
+
+/// Times `iterations` passes of the stage sequence, None if no samples were recorded.
+pub(crate) fn time_stages(
+    timer: &WgpuTimer,
+    stages: &[PreparedStage],
+    config: &BenchConfig,
+    iterations: usize,
+) -> anyhow::Result<Option<DispatchStats>> {
+    timer.configure(config);
+    for _ in 0..iterations {
+        dispatch_timed(timer, stages, config);
+    }
+    timer.flush()?;
+    Ok(timer.take_dispatch_stats())
 }
 
 pub fn benchmark<K: KernelBench>(
     c: &mut Criterion<&WgpuTimer>,
     timer: &WgpuTimer,
     kernel: K,
-    throughput: Throughput,
-) {
-    let handle = timer.handle();
-    let tensors = kernel.tensors();
-    kernel.validate(&tensors);
-    let workload = kernel.workload(&tensors);
-    let source = kernel.source(&workload);
-    let pipeline = source_to_pipeline(handle, &source);
-    let uniform_buffer = kernel.metadata(&tensors).into_buffer(handle);
+) -> Vec<BenchSummary> {
+    benchmark_with_config(c, timer, kernel, BenchConfig::default())
+}
 
-    let gpu_tensors = tensors
-        .into_iter()
-        .map(|t| t.into_gpu(handle))
-        .collect::<Vec<_>>();
-    let bind_groups = tensors_to_bind_groups(handle, &gpu_tensors, uniform_buffer, &pipeline);
+/// Benchmarks the kernel as configured, returning a summary per completed run.
+/// With `CacheMode::Cold`, a warm run is timed first so both can be compared.
+pub fn benchmark_with_config<K: KernelBench>(
+    c: &mut Criterion<&WgpuTimer>,
+    timer: &WgpuTimer,
+    kernel: K,
+    config: BenchConfig,
+) -> Vec<BenchSummary> {
+    config
+        .check()
+        .unwrap_or_else(|e| panic!("{}: {}", K::name(), e));
+    println!("{}: timing with {}", K::name(), timer.source());
+    let mut group = c.benchmark_group(K::name());
+    let parameter = timer.source().to_string();
+    let tensors = kernel.tensors();
+    let summaries = bench_kernel(&mut group, timer, &kernel, tensors, &config, &parameter);
+    group.finish();
+    report(timer.handle(), &config, &summaries);
+    summaries
+}
 
+/// Benchmarks every kernel configuration in one criterion group,
+/// identified by `KernelBench::parameter` so criterion can chart how the kernel scales.
+pub fn benchmark_sweep<K: KernelBench>(
+    c: &mut Criterion<&WgpuTimer>,
+    timer: &WgpuTimer,
+    kernels: impl IntoIterator<Item = K>,
+    config: BenchConfig,
+) -> Vec<BenchSummary> {
+    config
+        .check()
+        .unwrap_or_else(|e| panic!("{}: {}", K::name(), e));
+    println!("{}: timing with {}", K::name(), timer.source());
     let mut group = c.benchmark_group(K::name());
+    let mut summaries = vec![];
+    for kernel in kernels {
+        let parameter = kernel.parameter();
+        let tensors = kernel.tensors();
+        summaries.extend(bench_kernel(
+            &mut group, timer, &kernel, tensors, &config, &parameter,
+        ));
+    }
+    group.finish();
+    report(timer.handle(), &config, &summaries);
+    summaries
+}
+
+/// Validates and times a single kernel configuration on `tensors` within `group`.
+fn bench_kernel<K: KernelBench>(
+    group: &mut BenchmarkGroup<&WgpuTimer>,
+    timer: &WgpuTimer,
+    kernel: &K,
+    tensors: Vec<KernelTensor>,
+    config: &BenchConfig,
+    parameter: &str,
+) -> Vec<BenchSummary> {
+    let handle = timer.handle();
+    let (tensors, roles) = KernelTensor::split(tensors);
+    //Dump before anything can reject the stages, so failing shaders can be inspected too
+    let dump = config
+        .dump_dir
+        .clone()
+        .or_else(ShaderDump::root)
+        .map(|root| ShaderDump::new(root, &config.label(K::name()), parameter));
+    let stages = match &dump {
+        Some(dump) => dump.capture(|| kernel.stages(&tensors)),
+        None => kernel.stages(&tensors),
+    };
+    if let Some(dump) = dump {
+        match dump.write(&stages) {
+            Ok(()) => log::info!("Wrote shaders to {}", dump.dir().display()),
+            Err(e) => log::warn!("Dumping shaders to {}: {}", dump.dir().display(), e),
+        }
+    }
+    //Skip configurations the device can't run, rather than failing mid-sweep
+    let limits = handle.device().limits();
+    if let Err(e) = stages
+        .iter()
+        .try_for_each(|s| s.check_limits(&tensors, &limits).map(drop))
+    {
+        println!("{}/{}: skipped, {}", K::name(), parameter, e);
+        return vec![];
+    }
+    kernel.validate(handle, &tensors, &roles);
+    let work = kernel.work(&tensors);
+    let fallback_bytes = KernelWork::from_tensors(&tensors).bytes.unwrap_or_default();
+    let (stages, _gpu_tensors) = prepare(handle, kernel, tensors, &roles, config.dispatch);
+    if config.compile_timings {
+        for stage in &stages {
+            println!(
+                "{}/{} [{}]: module in {:.3} ms, pipeline in {:.3} ms",
+                K::name(),
+                parameter,
+                stage.label,
+                stage.module_time.as_secs_f64() * 1e3,
+                stage.pipeline_time.as_secs_f64() * 1e3
+            );
+        }
+    }
+
+    //Cold and indirect runs are compared against a warm, direct one
+    let baseline = BenchConfig {
+        cache: CacheMode::Warm,
+        dispatch: DispatchMode::Direct,
+        ..config.clone()
+    };
+    let runs = match (config.cache, config.dispatch) {
+        (CacheMode::Warm, DispatchMode::Direct) => vec![config.clone()],
+        _ => vec![baseline, config.clone()],
+    };
+
+    //Criterion keeps a group's throughput until replaced, so every kernel sets both lest it
+    //inherit the previous one's. Without declared work, fall back to bytes moved
+    let (throughput, element_kind) = work
+        .throughput()
+        .unwrap_or(KernelThroughput::Bytes(fallback_bytes))
+        .split();
+    timer.set_element_kind(element_kind);
     group.throughput(throughput);
-    group.bench_function(BenchmarkId::new(K::name(), 0), |b| {
-        b.iter(|| {
-            let tsw = timer.timestamp_writes();
-            dispatch(handle, &workload, &bind_groups, &pipeline, Some(tsw));
-            timer.increment_query();
+    let mut summaries = vec![];
+    for run_config in runs {
+        let label = run_config.label(K::name());
+        timer.configure(&run_config);
+        group.bench_function(BenchmarkId::new(&label, parameter), |b| {
+            b.iter(|| {
+                dispatch_timed(timer, &stages, &run_config);
+            });
         });
-    });
-    group.finish()
+        //Filtered out runs record no samples
+        let name = format!("{}/{}", label, parameter);
+        if let Some(stats) = timer.take_dispatch_stats() {
+            println!("{}: {}", name, stats);
+            if let (TimingSource::Timestamps, Some(host)) = (timer.source(), timer.take_host_mean())
+            {
+                println!(
+                    "{}: host {:.4} ns per dispatch, including submission",
+                    name, host
+                );
+            }
+            summaries.push(BenchSummary {
+                name,
+                mean_ns: stats.mean,
+                work,
+            });
+        }
+    }
+    if config.stage_timings && stages.len() > 1 {
+        report_stages(
+            timer,
+            &stages,
+            config,
+            &format!("{}/{}", K::name(), parameter),
+        );
+    }
+    summaries
+}
+
+/// Times each stage on its own, on whatever the full sequence left in the intermediates.
+fn report_stages(timer: &WgpuTimer, stages: &[PreparedStage], config: &BenchConfig, name: &str) {
+    for stage in stages {
+        let single = std::slice::from_ref(stage);
+        match time_stages(timer, single, config, STAGE_ITERATIONS) {
+            Ok(Some(stats)) => println!("{} [{}]: {}", name, stage.label, stats),
+            Ok(None) => {}
+            Err(e) => log::warn!("{} [{}]: {}", name, stage.label, e),
+        }
+    }
+}
+
+/// Prints the summary table, and the roofline if a device profile is available.
+fn report(handle: &GPUHandle, config: &BenchConfig, summaries: &[BenchSummary]) {
+    if summaries.is_empty() {
+        return;
+    }
+    println!("{}", summary_table(summaries));
+    report_roofline(handle, config, summaries);
+}
+
+fn report_roofline(handle: &GPUHandle, config: &BenchConfig, summaries: &[BenchSummary]) {
+    let profile = config
+        .profile
+        .clone()
+        .or_else(|| DeviceProfile::from_env(handle));
+    if let Some(profile) = profile {
+        for summary in summaries {
+            let roofline = Roofline::new(&profile, summary.work, summary.mean_ns * 1e-9);
+            println!("{}: {} ({})", summary.name, roofline, profile.name);
+        }
+    }
+}
+
+/// # Comparison
+///
+/// Several implementations of the same op, timed in one criterion group on the same tensors.
+pub struct Comparison<'a, 't> {
+    group: BenchmarkGroup<'a, &'t WgpuTimer>,
+    timer: &'t WgpuTimer,
+    tensors: Vec<KernelTensor>,
+    config: BenchConfig,
+    summaries: Vec<BenchSummary>,
+    baseline: Option<usize>,
+}
+
+impl<'a, 't> Comparison<'a, 't> {
+    /// Every kernel compared will be handed `tensors`, so their shapes must match its own.
+    pub fn new(
+        c: &'a mut Criterion<&'t WgpuTimer>,
+        timer: &'t WgpuTimer,
+        name: &str,
+        tensors: Vec<KernelTensor>,
+        config: BenchConfig,
+    ) -> Self {
+        config.check().unwrap_or_else(|e| panic!("{}: {}", name, e));
+        println!("{}: timing with {}", name, timer.source());
+        Self {
+            group: c.benchmark_group(name),
+            timer,
+            tensors,
+            config,
+            summaries: vec![],
+            baseline: None,
+        }
+    }
+
+    /// Times the kernel that speedups are reported against.
+    pub fn baseline<K: KernelBench>(&mut self, kernel: K) -> &mut Self {
+        let index = self.summaries.len();
+        self.bench(kernel);
+        if index < self.summaries.len() {
+            self.baseline = Some(index);
+        }
+        self
+    }
+
+    /// Validates and times the kernel on the shared tensors.
+    pub fn bench<K: KernelBench>(&mut self, kernel: K) -> &mut Self {
+        let shapes = |tensors: &[KernelTensor]| {
+            tensors
+                .iter()
+                .map(|t| (t.tensor.shape().clone(), t.role))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            shapes(&kernel.tensors()),
+            shapes(&self.tensors),
+            "{} expects different tensors or roles than the comparison provides",
+            K::name()
+        );
+        let parameter = self.timer.source().to_string();
+        let summaries = bench_kernel(
+            &mut self.group,
+            self.timer,
+            &kernel,
+            self.tensors.clone(),
+            &self.config,
+            &parameter,
+        );
+        self.summaries.extend(summaries);
+        self
+    }
+
+    /// Prints the kernels ranked fastest first, with their speedup over the baseline,
+    /// or over the first kernel timed if no baseline was given.
+    pub fn finish(self) -> Vec<BenchSummary> {
+        self.group.finish();
+        let baseline = self.baseline.unwrap_or_default();
+        if let Some(baseline) = self.summaries.get(baseline) {
+            println!("{}", comparison_table(&self.summaries, baseline));
+            report_roofline(self.timer.handle(), &self.config, &self.summaries);
+        }
+        self.summaries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BenchConfig, TimingMode, MAX_QUERY_PAIRS};
+
+    #[test]
+    pub fn oversized_passes_split() {
+        let per_dispatch = BenchConfig {
+            dispatches_per_pass: 4096 + 100,
+            timing: TimingMode::PerDispatch,
+            ..Default::default()
+        };
+        let max = MAX_QUERY_PAIRS as u64;
+        assert_eq!(per_dispatch.timed_passes(), vec![max, max, 100]);
+
+        //A pass timed as a whole takes one pair however many dispatches it holds
+        let pass = BenchConfig {
+            dispatches_per_pass: 10_000,
+            ..Default::default()
+        };
+        assert_eq!(pass.timed_passes(), vec![10_000]);
+    }
+
+    #[test]
+    pub fn empty_iterations_rejected() {
+        assert!(BenchConfig::default().check().is_ok());
+        let no_dispatches = BenchConfig {
+            dispatches_per_pass: 0,
+            ..Default::default()
+        };
+        assert!(no_dispatches.check().is_err());
+        let no_passes = BenchConfig {
+            passes_per_submit: 0,
+            ..Default::default()
+        };
+        assert!(no_passes.check().is_err());
+    }
+
+    #[test]
+    pub fn batched_passes_fit_the_ring() {
+        let max = MAX_QUERY_PAIRS as u64;
+        let batched = BenchConfig {
+            dispatches_per_pass: 1000,
+            passes_per_submit: 5,
+            timing: TimingMode::PerDispatch,
+            ..Default::default()
+        };
+        assert_eq!(
+            batched.timed_submissions(),
+            vec![vec![1000, 1000], vec![1000, 1000], vec![1000]]
+        );
+        let oversized = BenchConfig {
+            dispatches_per_pass: max + 1,
+            passes_per_submit: 2,
+            timing: TimingMode::PerDispatch,
+            ..Default::default()
+        };
+        assert_eq!(
+            oversized.timed_submissions(),
+            vec![vec![max], vec![1], vec![max], vec![1]]
+        );
+        let whole_passes = BenchConfig {
+            passes_per_submit: 4,
+            ..Default::default()
+        };
+        assert_eq!(whole_passes.timed_submissions().len(), 1);
+    }
 }
@@ -0,0 +1,58 @@
+use crate::GPUHandle;
+
+/// # CacheFlush
+///
+/// Pair of scratch buffers, each meant to be larger than the GPU's last level cache.
+/// Copying one over the other evicts whatever the previous dispatches left resident,
+/// which only holds if the cache is smaller than the scratch: size it from the device under test.
+#[derive(Debug)]
+pub struct CacheFlush {
+    src: wgpu::Buffer,
+    dst: wgpu::Buffer,
+    requested: u64,
+}
+
+impl CacheFlush {
+    /// Default size of each scratch buffer, above the last level caches of most current GPUs
+    /// but not all: some hold several hundred MiB.
+    pub const SCRATCH_BYTES: u64 = 128 * 1024 * 1024;
+
+    /// Scratch buffers of `bytes` each, clamped to the device's `max_buffer_size`.
+    pub fn new(handle: &GPUHandle, bytes: u64) -> Self {
+        let max = handle.device().limits().max_buffer_size;
+        let size = bytes.min(max) & !(wgpu::COPY_BUFFER_ALIGNMENT - 1);
+        let scratch = |usage| {
+            handle.device().create_buffer(&wgpu::BufferDescriptor {
+                label: Some("cache_flush"),
+                size,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+        Self {
+            src: scratch(wgpu::BufferUsages::COPY_SRC),
+            dst: scratch(wgpu::BufferUsages::COPY_DST),
+            requested: bytes,
+        }
+    }
+
+    /// Size asked for, before clamping.
+    pub fn requested(&self) -> u64 {
+        self.requested
+    }
+
+    pub fn encode(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.copy_buffer_to_buffer(&self.src, 0, &self.dst, 0, self.src.size());
+    }
+
+    /// Submits the flush on its own and blocks until it completes,
+    /// so it cannot overlap with anything submitted afterwards.
+    pub fn run(&self, handle: &GPUHandle) {
+        let mut encoder = handle
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.encode(&mut encoder);
+        handle.queue().submit(Some(encoder.finish()));
+        handle.device().poll(wgpu::Maintain::Wait);
+    }
+}
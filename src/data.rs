@@ -4,10 +4,11 @@ use wgpu::util::DeviceExt;
 
 use crate::GPUHandle;
 
-pub fn generate_weight_data<F: Float + bytemuck::Pod + std::fmt::Debug>(elements: usize) -> Vec<F>
+pub fn generate_weight_data<F: Float + bytemuck::Pod + std::fmt::Debug + SampleUniform>(
+    elements: usize,
+) -> Vec<F>
 where
     Standard: Distribution<F>,
-    F: SampleUniform,
 {
     let mut rng = rand::thread_rng();
     let dist = Uniform::from(F::from(-10.0).unwrap()..F::from(10.0).unwrap());
@@ -15,13 +16,12 @@ where
     x
 }
 
-pub fn empty_buffer<F: Float + bytemuck::Pod + std::fmt::Debug>(
+pub fn empty_buffer<F: Float + bytemuck::Pod + std::fmt::Debug + SampleUniform>(
     device: &wgpu::Device,
     elements: usize,
 ) -> wgpu::Buffer
 where
     Standard: Distribution<F>,
-    F: SampleUniform,
 {
     let data = vec![F::zero(); elements];
     device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -31,13 +31,12 @@ where
     })
 }
 
-pub fn rand_gpu_buffer<F: Float + bytemuck::Pod + std::fmt::Debug>(
+pub fn rand_gpu_buffer<F: Float + bytemuck::Pod + std::fmt::Debug + SampleUniform>(
     handle: &GPUHandle,
     elements: usize,
 ) -> wgpu::Buffer
 where
     Standard: Distribution<F>,
-    F: SampleUniform,
 {
     let data = generate_weight_data::<F>(elements);
     let buffer = handle
@@ -46,7 +46,7 @@ impl DType {
                 let aligner = |numel: usize, size_t: usize| -> usize {
                     let nbytes = numel * size_t;
 
-                    if nbytes % STORAGE_BUFFER_ALIGN != 0 {
+                    if !nbytes.is_multiple_of(STORAGE_BUFFER_ALIGN) {
                         nbytes + STORAGE_BUFFER_ALIGN - nbytes % STORAGE_BUFFER_ALIGN
                     } else {
                         nbytes
@@ -71,24 +71,29 @@ impl DType {
 }
 
 impl DType {
-    fn handle_type_str(ts: npyz::TypeStr) -> DType {
+    fn handle_type_str(ts: &npyz::TypeStr) -> anyhow::Result<DType> {
         match ts.endianness() {
             npyz::Endianness::Little => match (ts.type_char(), ts.size_field()) {
-                (npyz::TypeChar::Float, 4) => DType::F32,
-                (npyz::TypeChar::Int, 4) => DType::I32,
-                (npyz::TypeChar::Uint, 4) => DType::U32,
-                (t, s) => unimplemented!("{} {}", t, s),
+                (npyz::TypeChar::Float, 4) => Ok(DType::F32),
+                (npyz::TypeChar::Int, 4) => Ok(DType::I32),
+                (npyz::TypeChar::Uint, 4) => Ok(DType::U32),
+                _ => anyhow::bail!("Unsupported npy dtype {}", ts),
             },
-            _ => unimplemented!(),
+            _ => anyhow::bail!("Unsupported npy dtype {}, only little endian is read", ts),
         }
     }
 }
 
-impl From<npyz::DType> for DType {
-    fn from(dtype: npyz::DType) -> Self {
+impl TryFrom<npyz::DType> for DType {
+    type Error = anyhow::Error;
+
+    fn try_from(dtype: npyz::DType) -> anyhow::Result<Self> {
         match dtype {
-            npyz::DType::Plain(ts) => Self::handle_type_str(ts),
-            _ => unimplemented!(),
+            npyz::DType::Plain(ts) => Self::handle_type_str(&ts),
+            dtype => anyhow::bail!(
+                "Unsupported npy dtype {:?}, only plain types are read",
+                dtype
+            ),
         }
     }
 }
@@ -152,3 +157,17 @@ map_type!(i32, I32);
 map_type!(u32, U32);
 map_half_type!(f16, F16);
 map_half_type!(bf16, BF16);
+
+#[cfg(test)]
+mod tests {
+    use crate::DType;
+
+    #[test]
+    pub fn npy_dtypes() {
+        let plain = |descr: &str| npyz::DType::Plain(descr.parse().unwrap());
+        assert_eq!(DType::try_from(plain("<f4")).unwrap(), DType::F32);
+        assert_eq!(DType::try_from(plain("<u4")).unwrap(), DType::U32);
+        assert!(DType::try_from(plain("<f8")).is_err());
+        assert!(DType::try_from(plain(">f4")).is_err());
+    }
+}
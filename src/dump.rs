@@ -0,0 +1,241 @@
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+};
+
+use naga::back::{glsl, hlsl, msl, spv};
+use naga::valid::ModuleInfo;
+
+use crate::Stage;
+
+/// # ShaderDump
+///
+/// What every stage of one kernel configuration compiles from, under `<root>/<label>/<parameter>/`
+/// with the run's `BenchConfig::label`:
+/// the rendered WGSL, the tera context it was rendered from, and naga's SPIR-V, MSL, HLSL and GLSL
+/// translations of it. Handy to see what a template expanded to, or what a backend was handed.
+#[derive(Debug, Clone)]
+pub struct ShaderDump {
+    dir: PathBuf,
+}
+
+impl ShaderDump {
+    pub const ENV_VAR: &'static str = "WGPU_BENCH_DUMP";
+
+    /// Dump root from `WGPU_BENCH_DUMP`, None if unset.
+    pub fn root() -> Option<PathBuf> {
+        std::env::var_os(Self::ENV_VAR).map(PathBuf::from)
+    }
+
+    pub fn new(root: impl AsRef<Path>, label: &str, parameter: &str) -> Self {
+        Self {
+            dir: root
+                .as_ref()
+                .join(file_name(label))
+                .join(file_name(parameter)),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, name: &str, ext: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", file_name(name), ext))
+    }
+
+    /// Writes `<name>.wgsl` and, if given, its context as `<name>.json`.
+    fn write_source(
+        &self,
+        name: &str,
+        source: &str,
+        context: Option<&tera::Context>,
+    ) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(self.path(name, "wgsl"), source)?;
+        if let Some(context) = context {
+            let json = serde_json::to_string_pretty(&context.clone().into_json())?;
+            std::fs::write(self.path(name, "json"), json)?;
+        }
+        Ok(())
+    }
+
+    /// Writes `<stage>.wgsl` as handed to wgpu, `<stage>.json` holding its context,
+    /// `<stage>.constants.json` if it sets any, and a file per translation of the specialised module.
+    /// These go first: a stage naga rejects, or a translation it fails on, is logged and skipped.
+    pub fn write(&self, stages: &[Stage]) -> anyhow::Result<()> {
+        for stage in stages {
+            let path = |ext: &str| self.path(&stage.label, ext);
+            self.write_source(&stage.label, &stage.source, stage.context.as_ref())?;
+            if !stage.constants.is_empty() {
+                let json = serde_json::to_string_pretty(&stage.constants)?;
+                std::fs::write(path("constants.json"), json)?;
+            }
+            let (module, info) = match stage.specialized_module() {
+                Ok(specialized) => specialized,
+                Err(e) => {
+                    log::warn!("Not translating {}: {}", path("wgsl").display(), e);
+                    continue;
+                }
+            };
+            for (ext, translation) in translate(&module, &info, &stage.entry_point) {
+                match translation {
+                    Ok(bytes) => std::fs::write(path(ext), bytes)?,
+                    Err(e) => log::warn!("Not writing {}: {}", path(ext).display(), e),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `f`, typically building a kernel's stages, writing any render `render_wgsl` rejects
+    /// to this dump before its error can be unwrapped.
+    pub fn capture<T>(&self, f: impl FnOnce() -> T) -> T {
+        //Restored on unwind too, so a panicking kernel leaves no stale dump behind
+        struct Restore(Option<ShaderDump>);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CAPTURE.with(|dump| *dump.borrow_mut() = self.0.take());
+            }
+        }
+        let _restore = Restore(CAPTURE.with(|dump| dump.replace(Some(self.clone()))));
+        f()
+    }
+
+    /// Writes a render that failed validation as `<template>.wgsl` and `<template>.json`,
+    /// if within `capture`.
+    pub(crate) fn write_rejected(template: &str, source: &str, context: &tera::Context) {
+        CAPTURE.with(|dump| {
+            if let Some(dump) = &*dump.borrow() {
+                match dump.write_source(template, source, Some(context)) {
+                    Ok(()) => log::warn!(
+                        "Wrote rejected render to {}",
+                        dump.path(template, "wgsl").display()
+                    ),
+                    Err(e) => log::warn!("Dumping rejected render of {}: {}", template, e),
+                }
+            }
+        });
+    }
+}
+
+thread_local! {
+    static CAPTURE: RefCell<Option<ShaderDump>> = const { RefCell::new(None) };
+}
+
+/// The compute entry point translated by each of naga's backends, keyed by file extension.
+pub fn translate(
+    module: &naga::Module,
+    info: &ModuleInfo,
+    entry_point: &str,
+) -> Vec<(&'static str, anyhow::Result<Vec<u8>>)> {
+    vec![
+        ("spv", to_spirv(module, info, entry_point)),
+        ("metal", to_msl(module, info)),
+        ("hlsl", to_hlsl(module, info)),
+        ("glsl", to_glsl(module, info, entry_point)),
+    ]
+}
+
+fn to_spirv(
+    module: &naga::Module,
+    info: &ModuleInfo,
+    entry_point: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let pipeline = spv::PipelineOptions {
+        shader_stage: naga::ShaderStage::Compute,
+        entry_point: entry_point.to_string(),
+    };
+    let words = spv::write_vec(module, info, &spv::Options::default(), Some(&pipeline))?;
+    Ok(bytemuck::cast_slice(&words).to_vec())
+}
+
+fn to_msl(module: &naga::Module, info: &ModuleInfo) -> anyhow::Result<Vec<u8>> {
+    let (source, _) = msl::write_string(
+        module,
+        info,
+        &msl::Options::default(),
+        &msl::PipelineOptions::default(),
+    )?;
+    Ok(source.into_bytes())
+}
+
+fn to_hlsl(module: &naga::Module, info: &ModuleInfo) -> anyhow::Result<Vec<u8>> {
+    let mut source = String::new();
+    hlsl::Writer::new(&mut source, &hlsl::Options::default()).write(module, info, None)?;
+    Ok(source.into_bytes())
+}
+
+fn to_glsl(module: &naga::Module, info: &ModuleInfo, entry_point: &str) -> anyhow::Result<Vec<u8>> {
+    let mut source = String::new();
+    //GLSL holds a single entry point, compute needs ES 3.1 (the default) or desktop 4.3
+    let pipeline = glsl::PipelineOptions {
+        shader_stage: naga::ShaderStage::Compute,
+        entry_point: entry_point.to_string(),
+        multiview: None,
+    };
+    glsl::Writer::new(
+        &mut source,
+        module,
+        info,
+        &glsl::Options::default(),
+        &pipeline,
+        naga::proc::BoundsCheckPolicies::default(),
+    )?
+    .write()?;
+    Ok(source.into_bytes())
+}
+
+/// Keeps a kernel name or parameter usable as a single path component.
+fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{render_wgsl, translate, validate_module, ShaderDump};
+
+    #[test]
+    pub fn translations_written() {
+        let source = r#"
+@group(0) @binding(0)
+var<storage, read_write> Y: array<f32>;
+
+@compute @workgroup_size(64, 1, 1)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    Y[global_id.x] = 1f;
+}
+"#;
+        let (module, info) = validate_module(source).unwrap();
+        for (ext, translation) in translate(&module, &info, "main") {
+            let bytes = translation.unwrap_or_else(|e| panic!("{}: {}", ext, e));
+            assert!(!bytes.is_empty(), "{} is empty", ext);
+        }
+    }
+
+    #[test]
+    pub fn rejected_render_dumped() {
+        let root = std::env::temp_dir().join(format!("wgpu-bench-dump-{}", std::process::id()));
+        let dump = ShaderDump::new(&root, "broken", "0");
+        let mut tera = tera::Tera::default();
+        tera.add_raw_template("broken", "fn main() { let x = {{ value }}; }")
+            .unwrap();
+        let mut context = tera::Context::new();
+        context.insert("value", "undefined_name");
+        assert!(dump
+            .capture(|| render_wgsl(&tera, "broken", &context))
+            .is_err());
+        let source = std::fs::read_to_string(dump.dir().join("broken.wgsl")).unwrap();
+        assert!(source.contains("undefined_name"));
+        assert!(dump.dir().join("broken.json").exists());
+        //Nothing is written outside of `capture`
+        std::fs::remove_dir_all(&root).unwrap();
+        assert!(render_wgsl(&tera, "broken", &context).is_err());
+        assert!(!root.exists());
+    }
+}
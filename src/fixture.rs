@@ -0,0 +1,193 @@
+use std::path::{Path, PathBuf};
+
+use crate::{CPUTensor, KernelBench};
+
+/// # Fixture
+///
+/// Golden inputs and outputs for one kernel problem, stored as `.npy` files under
+/// `<root>/<kernel>/<shapes>[_<config>]_seed<seed>/`.
+/// Generated once from a reference, later runs validate against the stored outputs.
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    dir: PathBuf,
+}
+
+impl Fixture {
+    pub const ENV_VAR: &'static str = "WGPU_BENCH_FIXTURES";
+    /// Under `target/` so generated fixtures stay out of git, set `WGPU_BENCH_FIXTURES` to keep them.
+    pub const DEFAULT_PATH: &'static str = "target/fixtures";
+
+    /// Fixture root, `WGPU_BENCH_FIXTURES` if set.
+    pub fn root() -> PathBuf {
+        std::env::var(Self::ENV_VAR)
+            .unwrap_or_else(|_| Self::DEFAULT_PATH.to_string())
+            .into()
+    }
+
+    /// Identifies a problem by the shapes of its tensors, any `config` the shapes don't capture,
+    /// and the seed they were drawn with.
+    pub fn key(tensors: &[CPUTensor], config: &str, seed: u64) -> String {
+        let shapes = tensors
+            .iter()
+            .map(|t| {
+                t.shape()
+                    .to_vec()
+                    .iter()
+                    .map(|d| d.to_string())
+                    .collect::<Vec<_>>()
+                    .join("x")
+            })
+            .collect::<Vec<_>>()
+            .join("_");
+        match config {
+            "" => format!("{}_seed{}", shapes, seed),
+            config => format!("{}_{}_seed{}", shapes, config, seed),
+        }
+    }
+
+    /// Fixture for tensors drawn with `CPUTensor::randn`.
+    pub fn new(kernel: &str, tensors: &[CPUTensor]) -> Self {
+        Self::with_seed(kernel, "", tensors, CPUTensor::RANDN_SEED)
+    }
+
+    /// Fixture for a kernel's tensors drawn with `CPUTensor::randn`, keyed by `KernelBench::fixture_key`.
+    pub fn for_kernel<K: KernelBench>(kernel: &K, tensors: &[CPUTensor]) -> Self {
+        Self::with_seed(
+            K::name(),
+            &kernel.fixture_key(),
+            tensors,
+            CPUTensor::RANDN_SEED,
+        )
+    }
+
+    pub fn with_seed(kernel: &str, config: &str, tensors: &[CPUTensor], seed: u64) -> Self {
+        Self::in_dir(Self::root(), kernel, config, tensors, seed)
+    }
+
+    pub fn in_dir(
+        root: impl AsRef<Path>,
+        kernel: &str,
+        config: &str,
+        tensors: &[CPUTensor],
+        seed: u64,
+    ) -> Self {
+        Self {
+            dir: root
+                .as_ref()
+                .join(kernel)
+                .join(Self::key(tensors, config, seed)),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn exists(&self) -> bool {
+        self.output_path(0).exists()
+    }
+
+    fn input_path(&self, index: usize) -> PathBuf {
+        self.dir.join(format!("input_{}.npy", index))
+    }
+
+    fn output_path(&self, index: usize) -> PathBuf {
+        self.dir.join(format!("output_{}.npy", index))
+    }
+
+    /// Writes the inputs and outputs.
+    /// Inputs without an npy equivalent, e.g packed quantized weights, are not stored.
+    pub fn save(&self, inputs: &[CPUTensor], outputs: &[CPUTensor]) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        for (i, input) in inputs.iter().enumerate() {
+            if let Err(e) = input.write_npy(self.input_path(i)) {
+                log::warn!("Not storing input {} of {}: {}", i, self.dir.display(), e);
+            }
+        }
+        for (i, output) in outputs.iter().enumerate() {
+            output.write_npy(self.output_path(i))?;
+        }
+        Ok(())
+    }
+
+    /// Reads the stored outputs, after checking the stored inputs match the given ones.
+    pub fn load(&self, inputs: &[CPUTensor]) -> anyhow::Result<Vec<CPUTensor>> {
+        for (i, input) in inputs.iter().enumerate() {
+            let path = self.input_path(i);
+            if !path.exists() {
+                continue;
+            }
+            //Bytes rather than `all_close`, which reads every dtype as f32
+            let stored = CPUTensor::read_npy(&path)?;
+            if stored.dt() != input.dt()
+                || stored.shape() != input.shape()
+                || stored.storage().as_bytes() != input.storage().as_bytes()
+            {
+                anyhow::bail!(
+                    "Stale fixture {}: stored {:?} {:?} differs from the input",
+                    path.display(),
+                    stored.dt(),
+                    stored.shape()
+                );
+            }
+        }
+        let mut outputs = vec![];
+        while self.output_path(outputs.len()).exists() {
+            outputs.push(CPUTensor::read_npy(self.output_path(outputs.len()))?);
+        }
+        Ok(outputs)
+    }
+
+    /// Stored outputs if present, otherwise computes them with `reference` and stores them.
+    pub fn outputs_or_else(
+        &self,
+        inputs: &[CPUTensor],
+        reference: impl FnOnce() -> Vec<CPUTensor>,
+    ) -> anyhow::Result<Vec<CPUTensor>> {
+        if self.exists() {
+            return self.load(inputs);
+        }
+        let outputs = reference();
+        self.save(inputs, &outputs)?;
+        log::info!("Wrote fixture {}", self.dir.display());
+        Ok(outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{shape, CPUTensor, Fixture};
+
+    #[test]
+    pub fn fixture_roundtrip() {
+        let root = std::env::temp_dir().join(format!("wgpu-bench-fixture-{}", std::process::id()));
+        let inputs = vec![CPUTensor::randn::<f32>(shape![2, 8])];
+        let fixture = Fixture::in_dir(&root, "Double", "", &inputs, CPUTensor::RANDN_SEED);
+        assert!(fixture.dir().ends_with("Double/2x8_seed42"));
+        let scaled = Fixture::in_dir(&root, "Double", "eps1e-5", &inputs, CPUTensor::RANDN_SEED);
+        assert!(scaled.dir().ends_with("Double/2x8_eps1e-5_seed42"));
+
+        let double = || {
+            let v = inputs[0].to_vec::<f32>().unwrap();
+            let doubled = v.iter().map(|x| x * 2.).collect::<Vec<_>>();
+            vec![CPUTensor::from_slice(&doubled, shape![2, 8])]
+        };
+        let generated = fixture.outputs_or_else(&inputs, double).unwrap();
+        let loaded = fixture
+            .outputs_or_else(&inputs, || panic!("Fixture should be loaded"))
+            .unwrap();
+        generated[0].all_close(&loaded[0], 0., 0.).unwrap();
+
+        let changed = vec![CPUTensor::zeros::<f32>(shape![2, 8])];
+        assert!(fixture.load(&changed).is_err());
+
+        //Both NaN when viewed as f32, yet different counts
+        let counts = vec![CPUTensor::from_slice(&[0x7fc0_0000u32], shape![1])];
+        let fixture = Fixture::in_dir(&root, "Count", "", &counts, 0);
+        fixture.save(&counts, &counts).unwrap();
+        fixture.load(&counts).unwrap();
+        let bumped = vec![CPUTensor::from_slice(&[0x7fc0_0001u32], shape![1])];
+        assert!(fixture.load(&bumped).is_err());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -1,4 +1,7 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 
 use wgpu::Adapter;
 use wgpu::DeviceType;
@@ -17,6 +20,10 @@ pub struct GPUHandle {
 pub struct Inner {
     device: wgpu::Device,
     queue: wgpu::Queue,
+    info: wgpu::AdapterInfo,
+    adapter_limits: Limits,
+    /// Shader modules by source, so configurations differing only in pipeline constants share one.
+    modules: Mutex<HashMap<String, wgpu::ShaderModule>>,
 }
 
 impl std::ops::Deref for GPUHandle {
@@ -28,10 +35,11 @@ impl std::ops::Deref for GPUHandle {
 }
 
 impl GPUHandle {
-    fn get_features() -> wgpu::Features {
-        wgpu::Features::default()
-            | wgpu::Features::TIMESTAMP_QUERY
-            | wgpu::Features::SUBGROUP_COMPUTE
+    fn get_features(adapter: &Adapter) -> wgpu::Features {
+        //Without TIMESTAMP_QUERY the WgpuTimer falls back to the host clock
+        let optional =
+            wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::TIMESTAMP_QUERY_INSIDE_PASSES;
+        wgpu::Features::default() | wgpu::Features::SUBGROUP | (adapter.features() & optional)
     }
 
     pub async fn new() -> Result<Self, anyhow::Error> {
@@ -39,12 +47,13 @@ impl GPUHandle {
 
         let mut device_descriptor = wgpu::DeviceDescriptor {
             label: Some("rumble"),
-            required_features: Self::get_features(),
+            required_features: Self::get_features(&adapter),
             required_limits: Limits {
                 max_buffer_size: (2 << 29) - 1,
                 max_storage_buffer_binding_size: (2 << 29) - 1,
                 ..Default::default()
             },
+            memory_hints: wgpu::MemoryHints::Performance,
         };
         let device_request = adapter.request_device(&device_descriptor, None).await;
         let (device, queue) = if let Err(e) = device_request {
@@ -58,7 +67,13 @@ impl GPUHandle {
         };
 
         Ok(Self {
-            inner: Arc::new(Inner { device, queue }),
+            inner: Arc::new(Inner {
+                device,
+                queue,
+                info: adapter.get_info(),
+                adapter_limits: adapter.limits(),
+                modules: Mutex::default(),
+            }),
         })
     }
 
@@ -70,32 +85,62 @@ impl GPUHandle {
         &self.queue
     }
 
+    pub fn adapter_info(&self) -> &wgpu::AdapterInfo {
+        &self.info
+    }
+
+    /// What the adapter supports, such as its subgroup sizes.
+    /// The device's own limits only hold what was requested.
+    pub fn adapter_limits(&self) -> &Limits {
+        &self.adapter_limits
+    }
+
+    /// The module compiled from `source`, created on first use. The source should already be
+    /// validated with naga, wgpu's own runtime checks are skipped.
+    pub fn shader_module(&self, source: &str) -> wgpu::ShaderModule {
+        let mut modules = self.modules.lock().unwrap();
+        if let Some(module) = modules.get(source) {
+            return module.clone();
+        }
+        let module = unsafe {
+            self.device.create_shader_module_trusted(
+                wgpu::ShaderModuleDescriptor {
+                    label: None,
+                    source: wgpu::ShaderSource::Wgsl(source.into()),
+                },
+                wgpu::ShaderRuntimeChecks::unchecked(),
+            )
+        };
+        modules.insert(source.to_string(), module.clone());
+        module
+    }
+
     fn select_adapter() -> Adapter {
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            dx12_shader_compiler: wgpu::util::dx12_shader_compiler_from_env().unwrap_or_default(),
-            ..Default::default()
-        });
-        let backends = wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::PRIMARY);
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+        let backends = wgpu::Backends::from_env().unwrap_or(wgpu::Backends::PRIMARY);
 
         let adapter = {
             let mut most_performant_adapter = None;
             let mut current_score = -1;
 
-            instance.enumerate_adapters(backends).for_each(|adapter| {
-                let info = adapter.get_info();
-                let score = match info.device_type {
-                    DeviceType::DiscreteGpu => 5,
-                    DeviceType::Other => 4, //Other is usually discrete
-                    DeviceType::IntegratedGpu => 3,
-                    DeviceType::VirtualGpu => 2,
-                    DeviceType::Cpu => 1,
-                };
-
-                if score > current_score {
-                    most_performant_adapter = Some(adapter);
-                    current_score = score;
-                }
-            });
+            instance
+                .enumerate_adapters(backends)
+                .into_iter()
+                .for_each(|adapter| {
+                    let info = adapter.get_info();
+                    let score = match info.device_type {
+                        DeviceType::DiscreteGpu => 5,
+                        DeviceType::Other => 4, //Other is usually discrete
+                        DeviceType::IntegratedGpu => 3,
+                        DeviceType::VirtualGpu => 2,
+                        DeviceType::Cpu => 1,
+                    };
+
+                    if score > current_score {
+                        most_performant_adapter = Some(adapter);
+                        current_score = score;
+                    }
+                });
 
             if let Some(adapter) = most_performant_adapter {
                 adapter
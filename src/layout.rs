@@ -0,0 +1,111 @@
+use std::collections::BTreeSet;
+
+use crate::{CPUTensor, Storage};
+
+/// `@group(group) @binding(binding)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, derive_new::new)]
+pub struct BindingSlot {
+    pub group: u32,
+    pub binding: u32,
+}
+
+/// # BindingLayout
+///
+/// Where every resource of a kernel is bound.
+/// `storage` holds a slot per buffer segment, in tensor order. Most tensors are a single segment,
+/// quantized ones span several (e.g `WQ8` weights and absmax).
+/// `uniforms` holds a slot per uniform buffer, the first being the kernel's metadata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BindingLayout {
+    pub storage: Vec<BindingSlot>,
+    pub uniforms: Vec<BindingSlot>,
+}
+
+impl BindingLayout {
+    pub const BINDINGS_PER_GROUP: usize = 4;
+
+    /// The default layout: segments fill groups of 4 in order,
+    /// the uniforms follow in a group of their own.
+    pub fn packed(segments: usize, uniforms: usize) -> Self {
+        let per_group = Self::BINDINGS_PER_GROUP;
+        let storage = (0..segments)
+            .map(|i| BindingSlot::new((i / per_group) as _, (i % per_group) as _))
+            .collect();
+        let uniform_group = segments.div_ceil(per_group) as u32;
+        let uniforms = (0..uniforms)
+            .map(|i| BindingSlot::new(uniform_group, i as _))
+            .collect();
+        Self { storage, uniforms }
+    }
+
+    /// Checks the layout covers exactly the resources given, each in a slot of its own.
+    pub fn check(&self, segments: usize, uniforms: usize) -> anyhow::Result<()> {
+        if self.storage.len() != segments {
+            anyhow::bail!(
+                "Layout has {} storage slots for {} buffer segments",
+                self.storage.len(),
+                segments
+            );
+        }
+        if self.uniforms.len() != uniforms {
+            anyhow::bail!(
+                "Layout has {} uniform slots for {} uniforms",
+                self.uniforms.len(),
+                uniforms
+            );
+        }
+        let mut seen = BTreeSet::new();
+        for slot in self.storage.iter().chain(&self.uniforms) {
+            if !seen.insert(slot) {
+                anyhow::bail!(
+                    "@group({}) @binding({}) is used twice",
+                    slot.group,
+                    slot.binding
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of bind groups needed, gaps included.
+    pub fn group_count(&self) -> usize {
+        self.storage
+            .iter()
+            .chain(&self.uniforms)
+            .map(|slot| slot.group as usize + 1)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+/// Number of buffer segments the tensors are bound as.
+pub fn segment_count<'t>(tensors: impl IntoIterator<Item = &'t CPUTensor>) -> usize {
+    tensors
+        .into_iter()
+        .map(|t| {
+            t.dt()
+                .segments(t.shape().numel(), t.storage().n_bytes())
+                .len()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BindingLayout, BindingSlot};
+
+    #[test]
+    pub fn packed_layout() {
+        let layout = BindingLayout::packed(5, 1);
+        assert_eq!(layout.storage[3], BindingSlot::new(0, 3));
+        assert_eq!(layout.storage[4], BindingSlot::new(1, 0));
+        assert_eq!(layout.uniforms, vec![BindingSlot::new(2, 0)]);
+        assert_eq!(layout.group_count(), 3);
+        assert!(layout.check(5, 1).is_ok());
+        assert!(layout.check(4, 1).is_err());
+
+        let mut clash = BindingLayout::packed(2, 1);
+        clash.uniforms[0] = BindingSlot::new(0, 1);
+        assert!(clash.check(2, 1).is_err());
+    }
+}
@@ -1,35 +1,64 @@
-#![feature(int_roundings)]
 mod bench;
+mod cache;
 mod data;
 mod dtype;
+mod dump;
+mod fixture;
 mod handle;
+mod layout;
 mod metadata;
 mod quant;
+pub mod reference;
+mod report;
+mod resources;
+mod role;
+mod roofline;
 mod shape;
+mod stage;
+mod stats;
 mod storage;
 mod tensor;
+mod throughput;
+mod tune;
+mod wgsl;
 mod workload;
 
-use std::{cell::Cell, ops::Range};
+use std::{
+    cell::{Cell, RefCell},
+    ops::Range,
+    time::Instant,
+};
 
 pub use bench::*;
+pub use cache::*;
 pub use data::*;
 pub use dtype::*;
+pub use dump::*;
+pub use fixture::*;
 pub use handle::*;
+pub use layout::*;
 pub use metadata::*;
 pub use quant::*;
+pub use report::*;
+pub use resources::*;
+pub use role::*;
+pub use roofline::*;
 pub use shape::*;
+pub use stage::*;
+pub use stats::*;
 pub use storage::*;
 pub use tensor::*;
+pub use throughput::*;
+pub use tune::*;
+pub use wgsl::*;
 pub use workload::*;
 
-use criterion::{
-    measurement::{Measurement, ValueFormatter},
-    Throughput,
-};
+use criterion::measurement::{Measurement, ValueFormatter};
 use wgpu::QuerySet;
 
 pub const MAX_QUERIES: u32 = 4096;
+/// Timestamp pairs the query set holds, the most one submission can write.
+pub const MAX_QUERY_PAIRS: u32 = MAX_QUERIES / 2;
 
 /// Start and end index in the counter sample buffer
 #[derive(Debug, Clone, Copy)]
@@ -54,6 +83,19 @@ impl QueryPair {
     pub fn end_address(&self) -> wgpu::BufferAddress {
         ((self.end + 1) as usize * std::mem::size_of::<u64>()) as wgpu::BufferAddress
     }
+
+    /// The pair written after this one.
+    pub fn next(&self) -> Self {
+        Self {
+            start: self.start + 2,
+            end: self.end + 2,
+        }
+    }
+
+    /// Whether `pairs` more pairs, starting at this one, fit before the end of the query set.
+    pub fn has_room(&self, pairs: u32) -> bool {
+        self.start + pairs * 2 <= MAX_QUERIES
+    }
 }
 
 impl From<QueryPair> for Range<u32> {
@@ -62,22 +104,33 @@ impl From<QueryPair> for Range<u32> {
     }
 }
 
-pub struct WgpuTimer {
-    handle: GPUHandle,
+/// Where the timer takes its measurements from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingSource {
+    /// GPU timestamp queries written around each timed pass.
+    Timestamps,
+    /// Host wall-clock from `submit` until `poll(Wait)` returns.
+    /// Used when the adapter lacks `TIMESTAMP_QUERY`, includes submission overhead.
+    HostClock,
+}
+
+impl std::fmt::Display for TimingSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimingSource::Timestamps => write!(f, "gpu-timestamps"),
+            TimingSource::HostClock => write!(f, "host-clock"),
+        }
+    }
+}
+
+struct TimestampQueries {
     query_set: QuerySet,
     resolve_buffer: wgpu::Buffer,
     destination_buffer: wgpu::Buffer,
-    current_query: Cell<QueryPair>,
 }
 
-//TODO: dumb
-unsafe impl Send for WgpuTimer {}
-unsafe impl Sync for WgpuTimer {}
-
-impl WgpuTimer {
-    pub const COMPUTE_PER_QUERY: u64 = 100;
-
-    pub fn new(handle: GPUHandle) -> Self {
+impl TimestampQueries {
+    fn new(handle: &GPUHandle) -> Self {
         let query_set = handle.device().create_query_set(&wgpu::QuerySetDescriptor {
             count: MAX_QUERIES,
             ty: wgpu::QueryType::Timestamp,
@@ -101,169 +154,329 @@ impl WgpuTimer {
         });
 
         Self {
-            handle,
             query_set,
             resolve_buffer,
             destination_buffer,
+        }
+    }
+}
+
+/// # WgpuTimer
+///
+/// Criterion measurement for GPU kernels.
+/// Uses timestamp queries when the device supports them, falling back to the host clock otherwise.
+pub struct WgpuTimer {
+    handle: GPUHandle,
+    source: TimingSource,
+    queries: Option<TimestampQueries>,
+    current_query: Cell<QueryPair>,
+    accumulated: Cell<u64>, //Raw ticks (or host ns) from pairs resolved before the ring wrapped
+    dispatches_per_iter: Cell<u64>,
+    dispatches_per_query: Cell<u64>,
+    samples: RefCell<Vec<f64>>, //Raw ticks per dispatch, one entry per resolved pair
+    dropped: Cell<usize>,       //Invalid pairs discarded since the last configure
+    host_elapsed: Cell<u64>,    //Wall-clock ns from submit to idle, whatever the source
+    host_dispatches: Cell<u64>,
+    element_kind: Cell<ElementKind>,
+    cache_flush: RefCell<Option<CacheFlush>>, //Created on first cold-cache pass, it is large
+}
+
+//TODO: dumb
+unsafe impl Send for WgpuTimer {}
+unsafe impl Sync for WgpuTimer {}
+
+impl WgpuTimer {
+    pub const COMPUTE_PER_QUERY: u64 = 100;
+
+    pub fn new(handle: GPUHandle) -> Self {
+        let source = if handle
+            .device()
+            .features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+        {
+            TimingSource::Timestamps
+        } else {
+            log::warn!("TIMESTAMP_QUERY unavailable, timing with the host clock");
+            TimingSource::HostClock
+        };
+        let queries = match source {
+            TimingSource::Timestamps => Some(TimestampQueries::new(&handle)),
+            TimingSource::HostClock => None,
+        };
+
+        Self {
+            handle,
+            source,
+            queries,
             current_query: QueryPair::first().into(),
+            accumulated: Cell::new(0),
+            dispatches_per_iter: Cell::new(Self::COMPUTE_PER_QUERY),
+            dispatches_per_query: Cell::new(Self::COMPUTE_PER_QUERY),
+            samples: RefCell::new(vec![]),
+            dropped: Cell::new(0),
+            host_elapsed: Cell::new(0),
+            host_dispatches: Cell::new(0),
+            element_kind: Cell::new(ElementKind::default()),
+            cache_flush: RefCell::new(None),
         }
     }
 
+    /// Sets how many dispatches each iteration and each query pair covers,
+    /// and discards any per-dispatch samples from a previous benchmark.
+    pub fn configure(&self, config: &BenchConfig) {
+        self.dispatches_per_iter.set(config.dispatches_per_iter());
+        self.dispatches_per_query.set(config.dispatches_per_query());
+        self.samples.borrow_mut().clear();
+        self.dropped.set(0);
+        self.host_elapsed.set(0);
+        self.host_dispatches.set(0);
+    }
+
+    /// Sets whether `Throughput::Elements` counts are reported as elements or FLOPs.
+    pub fn set_element_kind(&self, kind: ElementKind) {
+        self.element_kind.set(kind);
+    }
+
+    /// Drains the per-dispatch samples collected so far into a summary.
+    pub fn take_dispatch_stats(&self) -> Option<DispatchStats> {
+        let period = self.period();
+        let samples = self
+            .samples
+            .take()
+            .into_iter()
+            .map(|ticks| ticks * period)
+            .collect();
+        DispatchStats::from_samples(samples).map(|stats| DispatchStats {
+            dropped: self.dropped.replace(0),
+            ..stats
+        })
+    }
+
+    /// Mean wall-clock nanoseconds per dispatch since the last call, including submission
+    /// and `poll(Wait)`. Compared with the GPU timings, shows how submit-bound a benchmark is.
+    pub fn take_host_mean(&self) -> Option<f64> {
+        let dispatches = self.host_dispatches.replace(0);
+        let elapsed = self.host_elapsed.replace(0);
+        (dispatches > 0).then(|| elapsed as f64 / dispatches as f64)
+    }
+
+    /// Nanoseconds per raw unit of the timing source.
+    fn period(&self) -> f64 {
+        match self.source {
+            TimingSource::Timestamps => self.handle.queue().get_timestamp_period() as f64,
+            TimingSource::HostClock => 1.0,
+        }
+    }
+
+    fn queries(&self) -> &TimestampQueries {
+        self.queries
+            .as_ref()
+            .expect("Timestamp queries require TIMESTAMP_QUERY")
+    }
+
     pub fn resolve_pass(&self, encoder: &mut wgpu::CommandEncoder, pass_query: QueryPair) {
+        let queries = self.queries();
         let resolution_range = pass_query.into();
         log::trace!("Resolution range: {:?}", resolution_range);
-        encoder.resolve_query_set(&self.query_set, resolution_range, &self.resolve_buffer, 0);
+        encoder.resolve_query_set(
+            &queries.query_set,
+            resolution_range,
+            &queries.resolve_buffer,
+            0,
+        );
         let size = pass_query.size();
         log::trace!("Resolution size in bytes: {:?}", size);
-        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &self.destination_buffer, 0, size);
+        encoder.copy_buffer_to_buffer(
+            &queries.resolve_buffer,
+            0,
+            &queries.destination_buffer,
+            0,
+            size,
+        );
     }
 
     pub fn handle(&self) -> &GPUHandle {
         &self.handle
     }
 
-    pub fn query_set(&self) -> &QuerySet {
-        &self.query_set
+    pub fn source(&self) -> TimingSource {
+        self.source
     }
 
-    pub fn increment_query(&self) {
-        let pair = self.current_query.get();
-        if pair.end + 2 >= MAX_QUERIES {
-            panic!("Number of queries exceeds MAX_QUERIES, reduce duration of benchmark");
+    /// Evicts the caches with a scratch copy of `bytes`, reallocated only when the size changes.
+    pub fn flush_cache(&self, bytes: u64) {
+        let mut flush = self.cache_flush.borrow_mut();
+        if !matches!(&*flush, Some(f) if f.requested() == bytes) {
+            *flush = Some(CacheFlush::new(&self.handle, bytes));
         }
-        self.current_query.set(QueryPair {
-            start: pair.start + 2,
-            end: pair.end + 2,
-        });
+        flush.as_ref().unwrap().run(&self.handle);
     }
 
-    pub fn current_query(&self) -> QueryPair {
-        self.current_query.get()
+    pub fn query_set(&self) -> &QuerySet {
+        &self.queries().query_set
     }
 
-    //Fetches the current query as ComputePassTimestampWrites
-    pub fn timestamp_writes(&self) -> wgpu::ComputePassTimestampWrites {
-        wgpu::ComputePassTimestampWrites {
-            query_set: &self.query_set,
-            beginning_of_pass_write_index: Some(self.current_query().start),
-            end_of_pass_write_index: Some(self.current_query().end),
+    /// Submits the command buffer holding `dispatches` timed dispatches,
+    /// and blocks until the device is idle.
+    /// The time spent in between is recorded, and is the measurement with the host clock.
+    pub fn submit(&self, command_buffer: wgpu::CommandBuffer, dispatches: u64) {
+        let start = Instant::now();
+        self.handle.queue().submit(Some(command_buffer));
+        self.handle.device().poll(wgpu::Maintain::Wait);
+        let elapsed = start.elapsed().as_nanos() as u64;
+        self.host_elapsed.set(self.host_elapsed.get() + elapsed);
+        self.host_dispatches
+            .set(self.host_dispatches.get() + dispatches);
+        if self.source == TimingSource::HostClock {
+            self.samples
+                .borrow_mut()
+                .push(elapsed as f64 / dispatches as f64);
+            self.accumulated.set(self.accumulated.get() + elapsed);
         }
     }
 
-    pub fn hardware_elapsed(&self, timestamps: &[u64]) -> u64 {
-        assert!(timestamps.len() % 2 == 0);
-        let mut elapsed = 0;
-        for i in (0..timestamps.len()).step_by(2) {
-            elapsed += timestamps[i + 1] - timestamps[i];
-        }
-        elapsed
+    pub fn increment_query(&self) {
+        self.current_query.set(self.current_query.get().next());
     }
-}
 
-impl Measurement for &WgpuTimer {
-    type Intermediate = u32; // Index of the start query
+    pub fn current_query(&self) -> QueryPair {
+        self.current_query.get()
+    }
 
-    type Value = u64; // Raw unscaled GPU counter
-                      // Must be multiplied by the timestamp period to get nanoseconds
+    /// Ensures the query set has room for `pairs` more query pairs.
+    ///
+    /// If the ring is too full, every pair written so far is resolved and
+    /// accumulated on the host, and writing restarts from the first query.
+    /// Must only be called once all previously written queries have been submitted.
+    /// Fails if `pairs` exceeds `MAX_QUERY_PAIRS`, or if the flush does.
+    pub fn reserve(&self, pairs: u32) -> anyhow::Result<()> {
+        if pairs > MAX_QUERY_PAIRS {
+            anyhow::bail!(
+                "Cannot reserve {} query pairs, the query set holds {}",
+                pairs,
+                MAX_QUERY_PAIRS
+            );
+        }
+        if !self.current_query().has_room(pairs) {
+            self.flush()?;
+        }
+        Ok(())
+    }
 
-    fn start(&self) -> Self::Intermediate {
-        log::trace!("\nQuery at start of pass: {:?}", self.current_query());
-        0
+    /// Resolves all written query pairs into the host-side accumulator
+    /// and rewinds the ring to the first query.
+    ///
+    /// Fails if too many of the resolved pairs are invalid to trust the rest.
+    pub fn flush(&self) -> anyhow::Result<()> {
+        let written = self.current_query().start;
+        if written == 0 {
+            return Ok(());
+        }
+        let pass_query = QueryPair {
+            start: 0,
+            end: written - 1,
+        };
+        log::debug!("Flushing query ring: {:?}", pass_query);
+        let timestamps = self.read_timestamps(pass_query);
+        self.current_query.set(QueryPair::first());
+        let pairs = self.hardware_elapsed(&timestamps)?;
+        if pairs.dropped > 0 {
+            log::warn!("Dropped {} invalid timestamp pairs", pairs.dropped);
+            self.dropped.set(self.dropped.get() + pairs.dropped);
+        }
+        let per_query = self.dispatches_per_query.get() as f64;
+        self.samples.borrow_mut().extend(
+            pairs
+                .durations
+                .iter()
+                .map(|&ticks| ticks as f64 / per_query),
+        );
+        self.accumulated
+            .set(self.accumulated.get() + pairs.elapsed());
+        Ok(())
     }
 
-    fn end(&self, start_index: Self::Intermediate) -> Self::Value {
-        log::trace!("\nQuery at end of pass: {:?}", self.current_query());
+    fn read_timestamps(&self, pass_query: QueryPair) -> Vec<u64> {
         let mut encoder = self
             .handle
             .device()
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-
-        //Large window, eg 0..512
-        let pass_query = QueryPair {
-            start: start_index,
-            end: self.current_query().end - 2, //decrement here to counteract last iter
-        };
-        log::trace!("Pass range: {:?}", pass_query);
-
         self.resolve_pass(&mut encoder, pass_query);
         self.handle().queue().submit(Some(encoder.finish()));
         self.handle.device().poll(wgpu::Maintain::Wait);
 
-        self.destination_buffer
+        let destination_buffer = &self.queries().destination_buffer;
+        destination_buffer
             .slice(..)
             .map_async(wgpu::MapMode::Read, |_| ());
         self.handle.device().poll(wgpu::Maintain::Wait);
         let timestamps: Vec<u64> = {
             let byte_range = pass_query.start_address()..pass_query.end_address();
-            let timestamp_view = self.destination_buffer.slice(byte_range).get_mapped_range();
+            let timestamp_view = destination_buffer.slice(byte_range).get_mapped_range();
             (*bytemuck::cast_slice(&timestamp_view)).to_vec()
         };
         log::trace!("Timestamps: {:?}", timestamps);
-        self.destination_buffer.unmap();
-        self.current_query.set(QueryPair::first());
-        self.hardware_elapsed(&timestamps) / WgpuTimer::COMPUTE_PER_QUERY
+        destination_buffer.unmap();
+        timestamps
     }
 
-    fn add(&self, v1: &Self::Value, v2: &Self::Value) -> Self::Value {
-        v1 + v2
+    //Fetches the current query as ComputePassTimestampWrites
+    //Wraps the ring if it is full, so call before encoding the pass
+    pub fn timestamp_writes(&self) -> wgpu::ComputePassTimestampWrites<'_> {
+        self.reserve(1)
+            .unwrap_or_else(|e| panic!("Refusing to report corrupted GPU timings: {}", e));
+        wgpu::ComputePassTimestampWrites {
+            query_set: self.query_set(),
+            beginning_of_pass_write_index: Some(self.current_query().start),
+            end_of_pass_write_index: Some(self.current_query().end),
+        }
     }
 
-    fn zero(&self) -> Self::Value {
-        0
+    pub fn hardware_elapsed(&self, timestamps: &[u64]) -> anyhow::Result<PairDurations> {
+        PairDurations::from_timestamps(timestamps)
     }
+}
 
-    fn to_f64(&self, value: &Self::Value) -> f64 {
-        (self.handle.queue().get_timestamp_period() as f64) * (*value as f64)
-    }
+impl Measurement for &WgpuTimer {
+    type Intermediate = (); // Timestamps are accumulated on the timer itself
 
-    fn formatter(&self) -> &dyn ValueFormatter {
-        &WgpuTimerFormatter
+    type Value = u64; // Raw unscaled GPU counter, or nanoseconds with the host clock
+                      // Must be multiplied by the timestamp period to get nanoseconds
+
+    fn start(&self) -> Self::Intermediate {
+        log::trace!("\nQuery at start of pass: {:?}", self.current_query());
+        self.accumulated.set(0);
     }
-}
 
-struct WgpuTimerFormatter;
-
-impl ValueFormatter for WgpuTimerFormatter {
-    fn format_value(&self, value: f64) -> String {
-        format!("{:.4} ns", value)
-    }
-
-    fn format_throughput(&self, throughput: &Throughput, value: f64) -> String {
-        match throughput {
-            Throughput::Bytes(b) => format!(
-                "{:.4} GiB/s",
-                (*b as f64) / (1024.0 * 1024.0 * 1024.0) / (value * 1e-9)
-            ),
-            Throughput::Elements(e) => {
-                let gflop = (*e as f64) / 1e9;
-                let seconds = value * 1e-9;
-                let gigaflop_per_second = gflop / seconds;
-                format!("{:.4} GFLOP/s", gigaflop_per_second)
-            }
-            _ => unreachable!(),
+    fn end(&self, _: Self::Intermediate) -> Self::Value {
+        log::trace!("\nQuery at end of pass: {:?}", self.current_query());
+        if let Err(e) = self.flush() {
+            panic!("Refusing to report corrupted GPU timings: {}", e);
         }
+        self.accumulated.replace(0) / self.dispatches_per_iter.get()
     }
 
-    fn scale_values(&self, _typical_value: f64, _values: &mut [f64]) -> &'static str {
-        "ns"
+    fn add(&self, v1: &Self::Value, v2: &Self::Value) -> Self::Value {
+        v1 + v2
     }
 
-    /// TODO!
-    fn scale_throughputs(
-        &self,
-        _typical_value: f64,
-        throughput: &Throughput,
-        _values: &mut [f64],
-    ) -> &'static str {
-        match throughput {
-            Throughput::Bytes(_) => "GiB/s",
-            Throughput::Elements(_) => "elements/s",
-            _ => unreachable!(),
-        }
+    fn zero(&self) -> Self::Value {
+        0
+    }
+
+    fn to_f64(&self, value: &Self::Value) -> f64 {
+        self.period() * (*value as f64)
     }
 
-    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
-        "ns"
+    fn formatter(&self) -> &dyn ValueFormatter {
+        match self.element_kind.get() {
+            ElementKind::Elements => &WgpuTimerFormatter {
+                elements: ElementKind::Elements,
+            },
+            ElementKind::Flops => &WgpuTimerFormatter {
+                elements: ElementKind::Flops,
+            },
+        }
     }
 }
 
@@ -284,4 +497,22 @@ mod tests {
         let query = QueryPair::first();
         assert_eq!(query.size(), 16);
     }
+
+    #[test]
+    pub fn ring_wraps_at_max_queries() {
+        let mut query = QueryPair::first();
+        for _ in 0..MAX_QUERIES / 2 - 1 {
+            assert!(query.has_room(1));
+            query = query.next();
+        }
+        //The last pair fills the query set exactly
+        assert_eq!((query.start, query.end), (MAX_QUERIES - 2, MAX_QUERIES - 1));
+        assert_eq!(query.end_address(), MAX_QUERIES as u64 * 8);
+        assert!(query.has_room(1));
+        assert!(!query.has_room(2));
+        //Once written, reserving flushes and rewinds before the next pass
+        assert!(!query.next().has_room(1));
+        assert!(QueryPair::first().has_room(MAX_QUERIES / 2));
+        assert!(!QueryPair::first().has_room(MAX_QUERIES / 2 + 1));
+    }
 }
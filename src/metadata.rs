@@ -7,6 +7,7 @@ pub const STORAGE_BUFFER_ALIGN: usize = 256;
 pub const MIN_STORAGE_BUFFER_SIZE: usize = 16;
 
 pub trait OpMetadata: Sized + ShaderType + WriteInto + std::fmt::Debug {
+    #[allow(clippy::wrong_self_convention)]
     fn into_buffer(&self, handle: &GPUHandle) -> GPUBuffer {
         let size: usize = self.size().get() as _;
         let aligned_size = size + (UNIFORM_ALIGN - size % UNIFORM_ALIGN);
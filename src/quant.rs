@@ -33,7 +33,7 @@ impl Quantizer {
     /// It's a pretty naive quantization scheme, more to come.
     pub fn sint8_quantize(&self, tensor: CPUTensor) -> CPUTensor {
         let numel = tensor.shape().numel();
-        assert!(numel % 4 == 0 && numel % 16 == 0);
+        assert!(numel.is_multiple_of(4) && numel.is_multiple_of(16));
         assert!(tensor.dt() == DType::F32); //TODO: f16, bf16
                                             //TODO: check if tensor is contiguous
         let pack_size = self.format.pack_size();
@@ -45,7 +45,7 @@ impl Quantizer {
         //returns the aligned number of ELEMENTS
         let aligner = |numel: usize, size_t: usize| -> usize {
             let nbytes = numel * size_t;
-            let aligned = if nbytes % STORAGE_BUFFER_ALIGN != 0 {
+            let aligned = if !nbytes.is_multiple_of(STORAGE_BUFFER_ALIGN) {
                 nbytes + STORAGE_BUFFER_ALIGN - nbytes % STORAGE_BUFFER_ALIGN
             } else {
                 nbytes
@@ -62,7 +62,7 @@ impl Quantizer {
         let matrix = tensor.to_vec::<f32>().unwrap();
 
         for i in (0..numel).step_by(pack_size) {
-            if i % group_size == 0 {
+            if i.is_multiple_of(group_size) {
                 block_absmax = matrix[i..i + group_size]
                     .iter()
                     .fold(f32::NEG_INFINITY, |acc, &x| acc.max(x.abs()));
@@ -74,7 +74,8 @@ impl Quantizer {
             quantized_matrix[i / pack_size] = packed_value as u32;
             absmax_matrix[i / group_size] = block_absmax;
         }
-        quantized_matrix.append(&mut unsafe { std::mem::transmute(absmax_matrix) });
+        quantized_matrix
+            .append(&mut unsafe { std::mem::transmute::<Vec<f32>, Vec<u32>>(absmax_matrix) });
         unsafe { CPUTensor::from_quantized(quantized_matrix, tensor.shape().clone(), DType::WQ8) }
     }
 
@@ -84,12 +85,11 @@ impl Quantizer {
 
         let aligner = |numel: usize, size_t: usize| -> usize {
             let nbytes = numel * size_t;
-            let aligned = if nbytes % STORAGE_BUFFER_ALIGN != 0 {
+            if !nbytes.is_multiple_of(STORAGE_BUFFER_ALIGN) {
                 nbytes + STORAGE_BUFFER_ALIGN - nbytes % STORAGE_BUFFER_ALIGN
             } else {
                 nbytes
-            };
-            aligned
+            }
         };
 
         let pack_size = self.format.pack_size();
@@ -0,0 +1,209 @@
+//! CPU ground truth for validating kernels.
+//!
+//! Inputs are f32 tensors, all arithmetic is carried out in f64 and rounded once on the way out,
+//! so accumulation error in the reference is negligible next to the kernel's.
+use ndarray::{s, Array3, ArrayD, ArrayViewMut1, Axis, Ix3};
+
+use crate::{CPUTensor, DType, Quantization, Quantizer};
+
+fn to_f64(tensor: &CPUTensor) -> ArrayD<f64> {
+    assert_eq!(tensor.dt(), DType::F32, "Reference expects F32 tensors");
+    unsafe { tensor.to_array_view_unchecked::<f32>() }.mapv(f64::from)
+}
+
+fn from_f64(array: ArrayD<f64>) -> CPUTensor {
+    CPUTensor::from(array.mapv(|v| v as f32).as_standard_layout().into_owned())
+}
+
+fn vector(tensor: &CPUTensor, len: usize) -> Vec<f64> {
+    let v = to_f64(tensor).into_raw_vec();
+    assert_eq!(v.len(), len, "Expected {} elements, got {}", len, v.len());
+    v
+}
+
+//Applies `f` to every row along the last dimension
+fn map_rows(input: &CPUTensor, f: impl Fn(ArrayViewMut1<f64>)) -> CPUTensor {
+    let mut x = to_f64(input);
+    let last = Axis(x.ndim() - 1);
+    x.lanes_mut(last).into_iter().for_each(f);
+    from_f64(x)
+}
+
+fn mean(row: &ArrayViewMut1<f64>) -> f64 {
+    row.sum() / row.len() as f64
+}
+
+/// `op(a) @ op(b)`, where `op` swaps the last two dimensions when the flag is set.
+/// Accepts [M, K] or [B, M, K] operands, a batch of 1 is broadcast.
+pub fn matmul(a: &CPUTensor, b: &CPUTensor, trans_a: bool, trans_b: bool) -> CPUTensor {
+    let batched = |t: &CPUTensor, trans: bool| {
+        let x = to_f64(t);
+        let mut x = match x.ndim() {
+            2 => x.insert_axis(Axis(0)),
+            3 => x,
+            r => panic!("matmul expects rank 2 or 3, got {}", r),
+        }
+        .into_dimensionality::<Ix3>()
+        .unwrap();
+        if trans {
+            x.swap_axes(1, 2);
+        }
+        x
+    };
+    let unbatched = a.shape().rank() == 2 && b.shape().rank() == 2;
+    let (x, y) = (batched(a, trans_a), batched(b, trans_b));
+    let ((xb, m, k), (yb, ky, n)) = (x.dim(), y.dim());
+    assert_eq!(k, ky, "Inner dimensions differ: {} != {}", k, ky);
+    assert!(
+        xb == yb || xb == 1 || yb == 1,
+        "Batch dimensions differ: {} != {}",
+        xb,
+        yb
+    );
+
+    let batch = xb.max(yb);
+    let mut result = Array3::<f64>::zeros((batch, m, n));
+    for i in 0..batch {
+        let xi = x.slice(s![if xb == 1 { 0 } else { i }, .., ..]);
+        let yi = y.slice(s![if yb == 1 { 0 } else { i }, .., ..]);
+        result.slice_mut(s![i, .., ..]).assign(&xi.dot(&yi));
+    }
+    let result = result.into_dyn();
+    from_f64(if unbatched {
+        result.index_axis_move(Axis(0), 0)
+    } else {
+        result
+    })
+}
+
+/// Matmul against SInt8 packed weights, `a @ dequantize(b)`.
+pub fn dequant_matmul(a: &CPUTensor, b: &CPUTensor) -> CPUTensor {
+    let dequantized = Quantizer::new(Quantization::SInt8).dequantize(b.clone());
+    matmul(a, &dequantized, false, false)
+}
+
+/// LayerNorm over the last dimension, with the biased variance.
+pub fn layernorm(input: &CPUTensor, scale: &CPUTensor, bias: &CPUTensor, eps: f32) -> CPUTensor {
+    let n = input.shape()[input.shape().rank() - 1];
+    let (scale, bias) = (vector(scale, n), vector(bias, n));
+    let eps = f64::from(eps);
+    map_rows(input, |mut row| {
+        let mu = mean(&row);
+        let var = row.iter().map(|x| (x - mu).powi(2)).sum::<f64>() / n as f64;
+        let rstd = 1. / (var + eps).sqrt();
+        row.iter_mut()
+            .enumerate()
+            .for_each(|(i, x)| *x = (*x - mu) * rstd * scale[i] + bias[i]);
+    })
+}
+
+/// RMSNorm over the last dimension.
+pub fn rms_norm(input: &CPUTensor, scale: &CPUTensor, eps: f32) -> CPUTensor {
+    let n = input.shape()[input.shape().rank() - 1];
+    let scale = vector(scale, n);
+    let eps = f64::from(eps);
+    map_rows(input, |mut row| {
+        let ms = row.iter().map(|x| x * x).sum::<f64>() / n as f64;
+        let rrms = 1. / (ms + eps).sqrt();
+        row.iter_mut()
+            .enumerate()
+            .for_each(|(i, x)| *x = *x * rrms * scale[i]);
+    })
+}
+
+/// Softmax over the last dimension, shifted by the row max.
+pub fn softmax(input: &CPUTensor) -> CPUTensor {
+    map_rows(input, |mut row| {
+        let max = row.fold(f64::NEG_INFINITY, |acc, &x| acc.max(x));
+        row.mapv_inplace(|x| (x - max).exp());
+        let sum = row.sum();
+        row.mapv_inplace(|x| x / sum);
+    })
+}
+
+/// Exact GELU, `x * Φ(x)`.
+pub fn gelu(input: &CPUTensor) -> CPUTensor {
+    let mut x = to_f64(input);
+    x.mapv_inplace(|x| 0.5 * x * (1. + libm::erf(x / std::f64::consts::SQRT_2)));
+    from_f64(x)
+}
+
+/// GELU with the tanh approximation most kernels implement.
+pub fn gelu_tanh(input: &CPUTensor) -> CPUTensor {
+    let c = (2. / std::f64::consts::PI).sqrt();
+    let mut x = to_f64(input);
+    x.mapv_inplace(|x| 0.5 * x * (1. + (c * (x + 0.044715 * x.powi(3))).tanh()));
+    from_f64(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{reference, shape, CPUTensor, Quantization, Quantizer};
+
+    #[test]
+    pub fn matmul_transposed() {
+        let a = CPUTensor::from_slice(&[1f32, 2., 3., 4., 5., 6.], shape![2, 3]);
+        let b = CPUTensor::from_slice(&[1f32, 0., 0., 1., 1., 1.], shape![3, 2]);
+        let expected = CPUTensor::from_slice(&[4f32, 5., 10., 11.], shape![2, 2]);
+        reference::matmul(&a, &b, false, false)
+            .all_close(&expected, 0., 0.)
+            .unwrap();
+
+        //Batched, with both operands stored transposed
+        let at = CPUTensor::from_slice(&[1f32, 4., 2., 5., 3., 6.], shape![1, 3, 2]);
+        let bt = CPUTensor::from_slice(&[1f32, 0., 1., 0., 1., 1.], shape![1, 2, 3]);
+        let expected = CPUTensor::from_slice(&[4f32, 5., 10., 11.], shape![1, 2, 2]);
+        reference::matmul(&at, &bt, true, true)
+            .all_close(&expected, 0., 0.)
+            .unwrap();
+    }
+
+    #[test]
+    pub fn normalized_rows() {
+        let input = CPUTensor::randn::<f32>(shape![4, 64]);
+        let ones = CPUTensor::from_slice(&[1f32; 64], shape![64]);
+        let zeros = CPUTensor::zeros::<f32>(shape![64]);
+        let normed = reference::layernorm(&input, &ones, &zeros, 0.).to_vec::<f32>();
+        for row in normed.unwrap().chunks(64) {
+            let mean = row.iter().sum::<f32>() / 64.;
+            let var = row.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / 64.;
+            assert!(mean.abs() < 1e-5 && (var - 1.).abs() < 1e-4);
+        }
+
+        let probs = reference::softmax(&input).to_vec::<f32>().unwrap();
+        for row in probs.chunks(64) {
+            assert!((row.iter().sum::<f32>() - 1.).abs() < 1e-6);
+        }
+
+        let rms = reference::rms_norm(&input, &ones, 0.).to_vec::<f32>();
+        for row in rms.unwrap().chunks(64) {
+            let ms = row.iter().map(|x| x * x).sum::<f32>() / 64.;
+            assert!((ms - 1.).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    pub fn gelu_values() {
+        let x = CPUTensor::from_slice(&[-3f32, 0., 1., 3.], shape![4]);
+        let expected = CPUTensor::from_slice(&[-0.00404951, 0., 0.8413447, 2.9959502], shape![4]);
+        reference::gelu(&x)
+            .all_close(&expected, 1e-6, 1e-6)
+            .unwrap();
+        reference::gelu_tanh(&x)
+            .all_close(&expected, 1e-3, 1e-3)
+            .unwrap();
+    }
+
+    #[test]
+    pub fn dequant_matmul_matches_dequantized() {
+        let a = CPUTensor::randn::<f32>(shape![8, 16]);
+        let b = CPUTensor::randn::<f32>(shape![16, 16]);
+        let quantizer = Quantizer::new(Quantization::SInt8);
+        let quantized = quantizer.quantize(b);
+        let expected =
+            reference::matmul(&a, &quantizer.dequantize(quantized.clone()), false, false);
+        reference::dequant_matmul(&a, &quantized)
+            .all_close(&expected, 0., 0.)
+            .unwrap();
+    }
+}
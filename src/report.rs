@@ -0,0 +1,120 @@
+use tabled::{settings::Style, Table, Tabled};
+
+use crate::KernelWork;
+
+/// Result of a single benchmark, with enough detail to compare
+/// memory-bound and compute-bound kernels side by side.
+#[derive(Debug, Clone)]
+pub struct BenchSummary {
+    pub name: String,
+    /// Mean time per dispatch in nanoseconds.
+    pub mean_ns: f64,
+    pub work: KernelWork,
+}
+
+impl BenchSummary {
+    pub fn gib_per_second(&self) -> Option<f64> {
+        self.work
+            .bytes
+            .map(|b| b as f64 / (1024.0 * 1024.0 * 1024.0) / (self.mean_ns * 1e-9))
+    }
+
+    pub fn gflop_per_second(&self) -> Option<f64> {
+        self.work
+            .flops
+            .map(|f| f as f64 / 1e9 / (self.mean_ns * 1e-9))
+    }
+}
+
+#[derive(Tabled)]
+struct SummaryRow {
+    #[tabled(rename = "Kernel")]
+    kernel: String,
+    #[tabled(rename = "Time (ns)")]
+    time: String,
+    #[tabled(rename = "FLOPs")]
+    flops: String,
+    #[tabled(rename = "GiB/s")]
+    bandwidth: String,
+    #[tabled(rename = "GFLOP/s")]
+    compute: String,
+}
+
+impl From<&BenchSummary> for SummaryRow {
+    fn from(summary: &BenchSummary) -> Self {
+        let fmt = |v: Option<f64>| v.map_or("-".to_string(), |v| format!("{:.4}", v));
+        Self {
+            kernel: summary.name.clone(),
+            time: format!("{:.4}", summary.mean_ns),
+            flops: summary
+                .work
+                .flops
+                .map_or("-".to_string(), |f| f.to_string()),
+            bandwidth: fmt(summary.gib_per_second()),
+            compute: fmt(summary.gflop_per_second()),
+        }
+    }
+}
+
+/// Renders summaries as a table of time, FLOPs per dispatch, GiB/s and GFLOP/s.
+/// Criterion reports FLOPs as elements, this table names them.
+pub fn summary_table(summaries: &[BenchSummary]) -> String {
+    let rows = summaries.iter().map(SummaryRow::from);
+    Table::new(rows).with(Style::modern()).to_string()
+}
+
+#[derive(Tabled)]
+struct ComparisonRow {
+    #[tabled(rename = "Rank")]
+    rank: usize,
+    #[tabled(inline)]
+    summary: SummaryRow,
+    #[tabled(rename = "Speedup")]
+    speedup: String,
+}
+
+/// Renders summaries fastest first, with the speedup of each over `baseline`.
+pub fn comparison_table(summaries: &[BenchSummary], baseline: &BenchSummary) -> String {
+    let mut ranked = summaries.iter().collect::<Vec<_>>();
+    ranked.sort_by(|a, b| a.mean_ns.total_cmp(&b.mean_ns));
+    let rows = ranked.into_iter().enumerate().map(|(i, s)| ComparisonRow {
+        rank: i + 1,
+        summary: SummaryRow::from(s),
+        speedup: format!("{:.2}x", baseline.mean_ns / s.mean_ns),
+    });
+    Table::new(rows).with(Style::modern()).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{comparison_table, summary_table, BenchSummary, KernelWork};
+
+    #[test]
+    pub fn summary_rates() {
+        let summary = BenchSummary {
+            name: "SGEMM".to_string(),
+            mean_ns: 1e6,
+            work: KernelWork {
+                bytes: Some(1 << 30),
+                flops: Some(2_000_000_000),
+            },
+        };
+        assert_eq!(summary.gib_per_second(), Some(1000.0));
+        assert_eq!(summary.gflop_per_second(), Some(2000.0));
+        assert!(summary_table(&[summary]).contains("2000000000"));
+    }
+
+    #[test]
+    pub fn comparison_ranked() {
+        let summary = |name: &str, mean_ns| BenchSummary {
+            name: name.to_string(),
+            mean_ns,
+            work: KernelWork::default(),
+        };
+        let summaries = [summary("Naive", 200.0), summary("Welford", 50.0)];
+        let table = comparison_table(&summaries, &summaries[0]);
+        assert!(table.find("Welford").unwrap() < table.find("Naive").unwrap());
+        assert!(table.contains("4.00x"));
+        assert!(table.contains("1.00x"));
+    }
+}
@@ -0,0 +1,178 @@
+use naga::valid::ModuleInfo;
+use naga::AddressSpace;
+
+/// WebGPU counts every workgroup variable as a multiple of 16 bytes.
+const WORKGROUP_VAR_ALIGN: u64 = 16;
+
+/// # ResourceUsage
+///
+/// What one compute entry point asks of the device, reflected from its naga module
+/// so a kernel over the device's `Limits` is caught before pipeline creation.
+/// Only the globals the entry point reaches are counted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+    pub workgroup_size: [u32; 3],
+    /// Bytes of `var<workgroup>` memory.
+    pub workgroup_memory: u64,
+    pub storage_buffers: u32,
+    pub uniform_buffers: u32,
+    /// Size in bytes of each uniform, in declaration order.
+    pub uniform_sizes: Vec<u64>,
+    /// Highest `@group` used, plus one.
+    pub bind_groups: u32,
+    /// Highest `@binding` used, plus one.
+    pub bindings_per_group: u32,
+}
+
+impl ResourceUsage {
+    pub fn of(module: &naga::Module, info: &ModuleInfo, entry_point: &str) -> anyhow::Result<Self> {
+        let (index, ep) = module
+            .entry_points
+            .iter()
+            .enumerate()
+            .find(|(_, ep)| ep.name == entry_point && ep.stage == naga::ShaderStage::Compute)
+            .ok_or_else(|| anyhow::anyhow!("No compute entry point `{}`", entry_point))?;
+        let function = info.get_entry_point(index);
+
+        let mut usage = Self {
+            workgroup_size: ep.workgroup_size,
+            ..Default::default()
+        };
+        for (handle, global) in module.global_variables.iter() {
+            if function[handle].is_empty() {
+                continue;
+            }
+            let size = module.types[global.ty].inner.size(module.to_ctx()) as u64;
+            match global.space {
+                AddressSpace::WorkGroup => {
+                    usage.workgroup_memory += size.next_multiple_of(WORKGROUP_VAR_ALIGN)
+                }
+                AddressSpace::Storage { .. } => usage.storage_buffers += 1,
+                AddressSpace::Uniform => {
+                    usage.uniform_buffers += 1;
+                    usage.uniform_sizes.push(size);
+                }
+                _ => {}
+            }
+            if let Some(binding) = &global.binding {
+                usage.bind_groups = usage.bind_groups.max(binding.group + 1);
+                usage.bindings_per_group = usage.bindings_per_group.max(binding.binding + 1);
+            }
+        }
+        Ok(usage)
+    }
+
+    pub fn invocations(&self) -> u32 {
+        self.workgroup_size.iter().product()
+    }
+
+    /// Checks every resource against the device limits, naming the first one exceeded.
+    pub fn check(&self, limits: &wgpu::Limits) -> anyhow::Result<()> {
+        let max_size = [
+            limits.max_compute_workgroup_size_x,
+            limits.max_compute_workgroup_size_y,
+            limits.max_compute_workgroup_size_z,
+        ];
+        if self
+            .workgroup_size
+            .iter()
+            .zip(max_size)
+            .any(|(s, m)| *s > m)
+        {
+            anyhow::bail!(
+                "@workgroup_size{:?} exceeds {:?}",
+                self.workgroup_size,
+                max_size
+            );
+        }
+        let checks = [
+            (
+                "invocations per workgroup",
+                self.invocations() as u64,
+                limits.max_compute_invocations_per_workgroup as u64,
+            ),
+            (
+                "bytes of workgroup memory",
+                self.workgroup_memory,
+                limits.max_compute_workgroup_storage_size as u64,
+            ),
+            (
+                "storage buffers per stage",
+                self.storage_buffers as u64,
+                limits.max_storage_buffers_per_shader_stage as u64,
+            ),
+            (
+                "uniform buffers per stage",
+                self.uniform_buffers as u64,
+                limits.max_uniform_buffers_per_shader_stage as u64,
+            ),
+            (
+                "bytes in one uniform",
+                self.uniform_sizes.iter().copied().max().unwrap_or_default(),
+                limits.max_uniform_buffer_binding_size as u64,
+            ),
+            (
+                "bind groups",
+                self.bind_groups as u64,
+                limits.max_bind_groups as u64,
+            ),
+            (
+                "bindings per group",
+                self.bindings_per_group as u64,
+                limits.max_bindings_per_bind_group as u64,
+            ),
+        ];
+        for (resource, used, limit) in checks {
+            if used > limit {
+                anyhow::bail!("Uses {} {}, the device allows {}", used, resource, limit);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{validate_module, ResourceUsage};
+
+    const SOURCE: &str = r#"
+@group(0) @binding(0)
+var<storage, read> X: array<f32>;
+
+@group(0) @binding(1)
+var<storage, read_write> Y: array<f32>;
+
+@group(1) @binding(0)
+var<uniform> N: vec4<u32>;
+
+var<workgroup> smem: array<f32, 1024>;
+var<workgroup> flag: u32;
+
+@compute @workgroup_size(256, 1, 1)
+fn main(@builtin(local_invocation_id) local_id: vec3<u32>) {
+    smem[local_id.x] = X[local_id.x];
+    flag = N.x;
+    workgroupBarrier();
+    Y[local_id.x] = smem[local_id.x];
+}
+"#;
+
+    #[test]
+    pub fn resource_usage_reflected() {
+        let (module, info) = validate_module(SOURCE).unwrap();
+        let usage = ResourceUsage::of(&module, &info, "main").unwrap();
+        assert_eq!(usage.workgroup_memory, 4096 + 16);
+        assert_eq!(usage.storage_buffers, 2);
+        assert_eq!(usage.uniform_sizes, vec![16]);
+        assert_eq!((usage.bind_groups, usage.bindings_per_group), (2, 2));
+
+        let limits = wgpu::Limits::downlevel_defaults();
+        assert!(usage.check(&limits).is_ok());
+        let small = wgpu::Limits {
+            max_compute_workgroup_storage_size: 4096,
+            ..limits
+        };
+        let error = usage.check(&small).unwrap_err().to_string();
+        assert!(error.contains("workgroup memory"), "{}", error);
+    }
+}
@@ -0,0 +1,253 @@
+use wgpu::BufferUsages;
+
+use crate::{segment_count, validate_module, BindingLayout, BindingSlot, CPUTensor, Storage};
+
+/// How a kernel uses one of its tensors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TensorRole {
+    Input,
+    Output,
+    InOut,
+    /// Written by one stage of a multi-stage kernel and read by a later one, never read back.
+    Intermediate,
+}
+
+impl TensorRole {
+    /// Only tensors the kernel writes are read back, so only they need `COPY_SRC`.
+    pub fn usage(&self) -> BufferUsages {
+        match self {
+            TensorRole::Input | TensorRole::Intermediate => {
+                BufferUsages::STORAGE | BufferUsages::COPY_DST
+            }
+            TensorRole::Output | TensorRole::InOut => {
+                BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC
+            }
+        }
+    }
+
+    /// Access mode the shader must declare for the tensor's bindings.
+    /// Intermediates are written by some stages and only read by others, so either is accepted.
+    pub fn access(&self) -> Option<StorageAccess> {
+        match self {
+            TensorRole::Input => Some(StorageAccess::Read),
+            TensorRole::Output | TensorRole::InOut => Some(StorageAccess::ReadWrite),
+            TensorRole::Intermediate => None,
+        }
+    }
+
+    /// Whether the kernel produces the tensor as a result, to be read back.
+    pub fn is_written(&self) -> bool {
+        matches!(self, TensorRole::Output | TensorRole::InOut)
+    }
+}
+
+/// A tensor tagged with how the kernel uses it.
+#[derive(Debug, Clone)]
+pub struct KernelTensor {
+    pub tensor: CPUTensor,
+    pub role: TensorRole,
+}
+
+impl KernelTensor {
+    pub fn input(tensor: CPUTensor) -> Self {
+        Self {
+            tensor,
+            role: TensorRole::Input,
+        }
+    }
+
+    pub fn output(tensor: CPUTensor) -> Self {
+        Self {
+            tensor,
+            role: TensorRole::Output,
+        }
+    }
+
+    pub fn inout(tensor: CPUTensor) -> Self {
+        Self {
+            tensor,
+            role: TensorRole::InOut,
+        }
+    }
+
+    pub fn intermediate(tensor: CPUTensor) -> Self {
+        Self {
+            tensor,
+            role: TensorRole::Intermediate,
+        }
+    }
+
+    /// Separates the tensors from their roles, keeping the binding order.
+    pub fn split(tensors: Vec<KernelTensor>) -> (Vec<CPUTensor>, Vec<TensorRole>) {
+        tensors.into_iter().map(|t| (t.tensor, t.role)).unzip()
+    }
+}
+
+/// Access mode of a `var<storage, ...>` declaration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageAccess {
+    Read,
+    ReadWrite,
+}
+
+/// A storage buffer declared by a shader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageBinding {
+    pub group: u32,
+    pub binding: u32,
+    pub access: StorageAccess,
+}
+
+impl From<naga::StorageAccess> for StorageAccess {
+    fn from(access: naga::StorageAccess) -> Self {
+        if access.contains(naga::StorageAccess::STORE) {
+            StorageAccess::ReadWrite
+        } else {
+            StorageAccess::Read
+        }
+    }
+}
+
+/// Every storage buffer a module declares, as naga resolved them.
+pub fn storage_bindings(module: &naga::Module) -> Vec<StorageBinding> {
+    module
+        .global_variables
+        .iter()
+        .filter_map(|(_, var)| match (var.space, &var.binding) {
+            (naga::AddressSpace::Storage { access }, Some(binding)) => Some(StorageBinding {
+                group: binding.group,
+                binding: binding.binding,
+                access: access.into(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Checks each tensor's role against the access mode the shader declares for its bindings,
+/// with the tensors bound as `BindingLayout::packed` lays them out.
+pub fn check_roles(
+    source: &str,
+    tensors: &[CPUTensor],
+    roles: &[TensorRole],
+) -> anyhow::Result<()> {
+    let (module, _) = validate_module(source)?;
+    let layout = BindingLayout::packed(segment_count(tensors), 0);
+    check_bound_roles(
+        &module,
+        tensors.iter().zip(roles.iter().copied()).enumerate(),
+        &layout.storage,
+    )
+}
+
+/// As `check_roles`, over `(index, (tensor, role))` in binding order, the index naming the tensor
+/// in errors. `slots` gives the binding of each buffer segment, as in `BindingLayout::storage`.
+/// A segment bound to a slot the module doesn't declare is an error.
+pub(crate) fn check_bound_roles<'t>(
+    module: &naga::Module,
+    bound: impl IntoIterator<Item = (usize, (&'t CPUTensor, TensorRole))>,
+    slots: &[BindingSlot],
+) -> anyhow::Result<()> {
+    let declared = storage_bindings(module);
+    let mut slots = slots.iter();
+    for (i, (tensor, role)) in bound {
+        let numel = tensor.shape().numel();
+        let segments = tensor.dt().segments(numel, tensor.storage().n_bytes());
+        for _ in segments {
+            let slot = slots
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("No binding for tensor {}", i))?;
+            let decl = declared
+                .iter()
+                .find(|d| d.group == slot.group && d.binding == slot.binding)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Tensor {} is bound to @group({}) @binding({}), which the shader doesn't declare as storage",
+                        i,
+                        slot.group,
+                        slot.binding
+                    )
+                })?;
+            if role.access().is_some_and(|access| access != decl.access) {
+                anyhow::bail!(
+                    "Tensor {} is {:?}, but @group({}) @binding({}) is declared {:?}",
+                    i,
+                    role,
+                    slot.group,
+                    slot.binding,
+                    decl.access
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        check_roles, shape, storage_bindings, validate_module, CPUTensor, StorageAccess,
+        StorageBinding, TensorRole,
+    };
+
+    const SOURCE: &str = r#"
+struct Meta {
+    N: u32,
+}
+
+@group(0) @binding(0)
+var<storage, read> X: array<f32>;
+
+@group(0)
+@binding(1) var<storage, read_write> Y: array<f32>;
+
+//@group(0) @binding(2) var<storage, read_write> Z: array<f32>;
+/* @group(0) @binding(3) var<storage, read_write> W: array<f32>; */
+
+@group(1) @binding(0)
+var<uniform> metadata: Meta;
+
+@compute @workgroup_size(1)
+fn main() {
+    Y[0] = X[0] + f32(metadata.N);
+}
+"#;
+
+    #[test]
+    pub fn parse_storage_bindings() {
+        let (module, _) = validate_module(SOURCE).unwrap();
+        assert_eq!(
+            storage_bindings(&module),
+            vec![
+                StorageBinding {
+                    group: 0,
+                    binding: 0,
+                    access: StorageAccess::Read
+                },
+                StorageBinding {
+                    group: 0,
+                    binding: 1,
+                    access: StorageAccess::ReadWrite
+                },
+            ]
+        );
+    }
+
+    #[test]
+    pub fn roles_match_access() {
+        let tensors = vec![
+            CPUTensor::zeros::<f32>(shape![4]),
+            CPUTensor::zeros::<f32>(shape![4]),
+        ];
+        let roles = [TensorRole::Input, TensorRole::Output];
+        assert!(check_roles(SOURCE, &tensors, &roles).is_ok());
+        let swapped = [TensorRole::Output, TensorRole::Input];
+        assert!(check_roles(SOURCE, &tensors, &swapped).is_err());
+
+        //The third tensor lands on the commented out @binding(2)
+        let mut extra = tensors.clone();
+        extra.push(CPUTensor::zeros::<f32>(shape![4]));
+        let roles = [TensorRole::Input, TensorRole::Output, TensorRole::Output];
+        assert!(check_roles(SOURCE, &extra, &roles).is_err());
+    }
+}
@@ -0,0 +1,310 @@
+use std::{path::Path, sync::OnceLock};
+
+use encase::ShaderType;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    kernel_tera, measure, render_wgsl, shape, wgs, BenchConfig, CPUTensor, GPUHandle, KernelBench,
+    KernelTensor, KernelWork, OpMetadata, TensorRole, WgpuTimer, Workload,
+};
+
+/// # DeviceProfile
+///
+/// Peak capabilities of a device, the ceilings of the roofline model.
+/// Loaded from a JSON file, or estimated with microbenchmarks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceProfile {
+    pub name: String,
+    /// Peak memory bandwidth in bytes/s.
+    pub peak_bandwidth: f64,
+    /// Peak f32 throughput in FLOP/s.
+    pub peak_flops: f64,
+}
+
+impl DeviceProfile {
+    /// Path to a profile JSON file, or `measure` to run the microbenchmarks.
+    pub const ENV_VAR: &'static str = "WGPU_BENCH_PROFILE";
+    const PROBE_ITERS: usize = 10;
+
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let file = std::fs::File::create(path)?;
+        Ok(serde_json::to_writer_pretty(file, self)?)
+    }
+
+    /// Loads the profile named by `WGPU_BENCH_PROFILE`, at most once per process.
+    pub fn from_env(handle: &GPUHandle) -> Option<Self> {
+        static PROFILE: OnceLock<Option<DeviceProfile>> = OnceLock::new();
+        PROFILE
+            .get_or_init(|| {
+                let value = std::env::var(Self::ENV_VAR).ok()?;
+                let profile = if value == "measure" {
+                    Self::measure(handle)
+                } else {
+                    Self::from_file(&value)
+                };
+                profile
+                    .map_err(|e| log::warn!("Failed to load device profile: {}", e))
+                    .ok()
+            })
+            .clone()
+    }
+
+    /// Estimates peak bandwidth with a vec4 copy, and peak compute with chains of vec4 FMAs.
+    /// Both take the fastest dispatch observed.
+    pub fn measure(handle: &GPUHandle) -> anyhow::Result<Self> {
+        let timer = WgpuTimer::new(handle.clone());
+        let config = BenchConfig {
+            dispatches_per_pass: 10,
+            ..Default::default()
+        };
+
+        let copy = CopyProbe {
+            numel: 4 * 1024 * 1024, //vec4s, 64MiB per buffer
+        };
+        let copy_stats = measure(&timer, &copy, &config, Self::PROBE_ITERS)?;
+        let peak_bandwidth = copy.bytes() as f64 / (copy_stats.min * 1e-9);
+
+        let fma = FmaProbe {
+            invocations: 1024 * 1024,
+            iterations: 256,
+        };
+        let fma_stats = measure(&timer, &fma, &config, Self::PROBE_ITERS)?;
+        let peak_flops = fma.flops() as f64 / (fma_stats.min * 1e-9);
+
+        let profile = Self {
+            name: handle.adapter_info().name.clone(),
+            peak_bandwidth,
+            peak_flops,
+        };
+        log::info!("Measured device profile: {:?}", profile);
+        Ok(profile)
+    }
+
+    /// Arithmetic intensity (FLOP/B) at which the bandwidth and compute roofs meet.
+    pub fn ridge_point(&self) -> f64 {
+        self.peak_flops / self.peak_bandwidth
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Memory,
+    Compute,
+}
+
+/// Where a kernel sits relative to the device roofline.
+#[derive(Debug, Clone)]
+pub struct Roofline {
+    /// FLOP/B, only known when both bytes and FLOPs are.
+    pub intensity: Option<f64>,
+    pub bandwidth_fraction: Option<f64>,
+    pub compute_fraction: Option<f64>,
+    pub bound: Option<Bound>,
+}
+
+impl Roofline {
+    pub fn new(profile: &DeviceProfile, work: KernelWork, seconds: f64) -> Self {
+        let bandwidth_fraction = work
+            .bytes
+            .map(|b| b as f64 / seconds / profile.peak_bandwidth);
+        let compute_fraction = work.flops.map(|f| f as f64 / seconds / profile.peak_flops);
+        let intensity = work.bytes.zip(work.flops).map(|(b, f)| f as f64 / b as f64);
+        let bound = intensity.map(|i| {
+            if i < profile.ridge_point() {
+                Bound::Memory
+            } else {
+                Bound::Compute
+            }
+        });
+        Self {
+            intensity,
+            bandwidth_fraction,
+            compute_fraction,
+            bound,
+        }
+    }
+}
+
+impl std::fmt::Display for Roofline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let percent =
+            |v: Option<f64>| v.map_or("n/a".to_string(), |v| format!("{:.2}%", v * 100.0));
+        match self.intensity {
+            Some(i) => write!(f, "roofline: {:.4} FLOP/B", i)?,
+            None => write!(f, "roofline: n/a FLOP/B")?,
+        }
+        write!(
+            f,
+            ", {} of peak bandwidth, {} of peak compute",
+            percent(self.bandwidth_fraction),
+            percent(self.compute_fraction)
+        )?;
+        match self.bound {
+            Some(Bound::Memory) => write!(f, ", memory-bound"),
+            Some(Bound::Compute) => write!(f, ", compute-bound"),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(ShaderType, Debug)]
+pub(crate) struct CopyProbeMeta {
+    numel: u32,
+}
+
+impl OpMetadata for CopyProbeMeta {}
+
+#[derive(Debug)]
+struct CopyProbe {
+    numel: usize,
+}
+
+impl CopyProbe {
+    fn bytes(&self) -> u64 {
+        2 * (self.numel * 4 * std::mem::size_of::<f32>()) as u64
+    }
+}
+
+impl KernelBench for CopyProbe {
+    type Metadata = CopyProbeMeta;
+
+    fn name() -> &'static str {
+        "CopyProbe"
+    }
+
+    fn source(&self, workload: &Workload) -> String {
+        let mut tera = kernel_tera();
+        tera.add_raw_template(Self::name(), include_str!("../kernels/roofline/copy.wgsl"))
+            .unwrap();
+        render_wgsl(&tera, Self::name(), &self.context(workload)).unwrap()
+    }
+
+    fn tensors(&self) -> Vec<KernelTensor> {
+        let input = CPUTensor::zeros::<f32>(shape![self.numel * 4]);
+        let output = CPUTensor::zeros::<f32>(shape![self.numel * 4]);
+        vec![KernelTensor::input(input), KernelTensor::output(output)]
+    }
+
+    fn workload(&self, tensors: &[CPUTensor]) -> Workload {
+        //A vec4 per invocation
+        Workload::builder(tensors[0].shape(), wgs![256, 1, 1])
+            .elements_per_invocation(4, 1)
+            .linear()
+    }
+
+    fn metadata(&self, _: &[CPUTensor]) -> Self::Metadata {
+        CopyProbeMeta {
+            numel: self.numel as _,
+        }
+    }
+
+    fn validate(&self, _: &GPUHandle, _: &[CPUTensor], _: &[TensorRole]) {}
+}
+
+#[derive(ShaderType, Debug)]
+pub(crate) struct FmaProbeMeta {
+    iterations: u32,
+}
+
+impl OpMetadata for FmaProbeMeta {}
+
+#[derive(Debug)]
+struct FmaProbe {
+    invocations: usize,
+    iterations: usize,
+}
+
+impl FmaProbe {
+    fn flops(&self) -> u64 {
+        (self.invocations * self.iterations * 32) as u64
+    }
+}
+
+impl KernelBench for FmaProbe {
+    type Metadata = FmaProbeMeta;
+
+    fn name() -> &'static str {
+        "FmaProbe"
+    }
+
+    fn source(&self, workload: &Workload) -> String {
+        let mut tera = kernel_tera();
+        tera.add_raw_template(Self::name(), include_str!("../kernels/roofline/fma.wgsl"))
+            .unwrap();
+        render_wgsl(&tera, Self::name(), &self.context(workload)).unwrap()
+    }
+
+    fn tensors(&self) -> Vec<KernelTensor> {
+        let output = CPUTensor::zeros::<f32>(shape![self.invocations * 4]);
+        vec![KernelTensor::output(output)]
+    }
+
+    fn workload(&self, tensors: &[CPUTensor]) -> Workload {
+        //A vec4 per invocation
+        Workload::builder(tensors[0].shape(), wgs![256, 1, 1])
+            .elements_per_invocation(4, 1)
+            .linear()
+    }
+
+    fn metadata(&self, _: &[CPUTensor]) -> Self::Metadata {
+        FmaProbeMeta {
+            iterations: self.iterations as _,
+        }
+    }
+
+    fn validate(&self, _: &GPUHandle, _: &[CPUTensor], _: &[TensorRole]) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CopyProbe, FmaProbe};
+    use crate::{check_kernel_sources, Bound, DeviceProfile, KernelWork, Roofline};
+
+    #[test]
+    pub fn probe_sources_validate() {
+        check_kernel_sources(&CopyProbe { numel: 1024 }).unwrap();
+        check_kernel_sources(&FmaProbe {
+            invocations: 1024,
+            iterations: 8,
+        })
+        .unwrap();
+    }
+
+    #[test]
+    pub fn roofline_bound() {
+        let profile = DeviceProfile {
+            name: "test".to_string(),
+            peak_bandwidth: 400e9,
+            peak_flops: 10e12,
+        };
+        assert_eq!(profile.ridge_point(), 25.0);
+
+        let layernorm = KernelWork {
+            bytes: Some(200_000_000),
+            flops: Some(400_000_000),
+        };
+        let roofline = Roofline::new(&profile, layernorm, 1e-3);
+        assert_eq!(roofline.intensity, Some(2.0));
+        assert_eq!(roofline.bandwidth_fraction, Some(0.5));
+        assert_eq!(roofline.bound, Some(Bound::Memory));
+
+        let gemm = KernelWork {
+            bytes: Some(50_000_000),
+            flops: Some(5_000_000_000),
+        };
+        let roofline = Roofline::new(&profile, gemm, 1e-3);
+        assert_eq!(roofline.compute_fraction, Some(0.5));
+        assert_eq!(roofline.bound, Some(Bound::Compute));
+
+        let bytes_only = KernelWork {
+            bytes: Some(1),
+            flops: None,
+        };
+        assert_eq!(Roofline::new(&profile, bytes_only, 1.0).bound, None);
+    }
+}
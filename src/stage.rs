@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+
+use crate::{
+    check_bound_roles, segment_count, specialize, validate_module, BindingLayout, CPUTensor, DType,
+    ResourceUsage, Storage, TensorRole, Workload,
+};
+
+/// Where an indirect dispatch reads its workgroup counts: three `u32`s `offset` bytes into
+/// one of the kernel's tensors, written on the GPU by an earlier stage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndirectArgs {
+    pub tensor: usize,
+    pub offset: u64,
+}
+
+impl IndirectArgs {
+    /// Bytes read by `dispatch_workgroups_indirect`.
+    pub const SIZE: u64 = 3 * std::mem::size_of::<u32>() as u64;
+}
+
+/// # Stage
+///
+/// One pipeline of a multi-stage kernel, such as either pass of a two-pass reduction.
+/// Stages share the kernel's tensors, each binding its own subset, and are dispatched in order.
+#[derive(Debug, Clone, derive_new::new)]
+pub struct Stage {
+    /// Names the stage in per-stage timings.
+    pub label: String,
+    pub source: String,
+    pub entry_point: String,
+    pub workload: Workload,
+    /// Indices into the kernel's tensors, bound in this order.
+    pub bindings: Vec<usize>,
+    /// Where the bound tensors and the kernel's uniforms go, `BindingLayout::packed` if None.
+    #[new(default)]
+    pub layout: Option<BindingLayout>,
+    /// Context `source` was rendered from, written alongside it in shader dumps.
+    #[new(default)]
+    pub context: Option<tera::Context>,
+    /// Values for the source's `override` declarations, passed as pipeline constants.
+    #[new(default)]
+    pub constants: HashMap<String, f64>,
+    /// Dispatches with the counts found here rather than `workload`'s, which then only sets the
+    /// workgroup size and the largest count expected, e.g. for limit checks.
+    #[new(default)]
+    pub indirect: Option<IndirectArgs>,
+}
+
+impl Stage {
+    pub fn with_layout(mut self, layout: BindingLayout) -> Self {
+        self.layout = Some(layout);
+        self
+    }
+
+    pub fn with_context(mut self, context: tera::Context) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    pub fn with_constants(mut self, constants: HashMap<String, f64>) -> Self {
+        self.constants = constants;
+        self
+    }
+
+    pub fn with_indirect(mut self, tensor: usize, offset: u64) -> Self {
+        self.indirect = Some(IndirectArgs { tensor, offset });
+        self
+    }
+
+    /// Checks the indirect args, if any, fit within a `u32` tensor at a 4 byte aligned offset.
+    pub fn check_indirect(&self, tensors: &[CPUTensor]) -> anyhow::Result<()> {
+        let Some(args) = self.indirect else {
+            return Ok(());
+        };
+        let check = || {
+            let tensor = tensors.get(args.tensor).ok_or_else(|| {
+                anyhow::anyhow!("No tensor {} to read indirect args", args.tensor)
+            })?;
+            if tensor.dt() != DType::U32 {
+                anyhow::bail!(
+                    "Indirect args tensor {} is {:?}, not U32",
+                    args.tensor,
+                    tensor.dt()
+                );
+            }
+            if !args.offset.is_multiple_of(4) {
+                anyhow::bail!("Indirect args offset {} isn't 4 byte aligned", args.offset);
+            }
+            let n_bytes = tensor.storage().n_bytes() as u64;
+            if args.offset + IndirectArgs::SIZE > n_bytes {
+                anyhow::bail!(
+                    "Indirect args at {} overrun tensor {} of {} bytes",
+                    args.offset,
+                    args.tensor,
+                    n_bytes
+                );
+            }
+            Ok(())
+        };
+        check().map_err(|e: anyhow::Error| anyhow::anyhow!("Stage {}: {}", self.label, e))
+    }
+
+    /// The validated module with `constants` applied to its `override` declarations,
+    /// as the pipeline will see it.
+    pub fn specialized_module(&self) -> anyhow::Result<(naga::Module, naga::valid::ModuleInfo)> {
+        specialize(&self.source, &self.constants)
+            .map_err(|e| anyhow::anyhow!("Stage {}: {}", self.label, e))
+    }
+
+    /// The stage's layout, given the kernel's tensors and its number of uniforms.
+    pub fn layout(&self, tensors: &[CPUTensor], uniforms: usize) -> BindingLayout {
+        self.layout.clone().unwrap_or_else(|| {
+            let segments = segment_count(self.bindings.iter().map(|&i| &tensors[i]));
+            BindingLayout::packed(segments, uniforms)
+        })
+    }
+
+    /// The stage's layout, after checking it covers the stage's tensors and the kernel's uniforms,
+    /// and that the tensors' roles agree with the access modes its source declares.
+    pub fn checked_layout(
+        &self,
+        tensors: &[CPUTensor],
+        roles: &[TensorRole],
+        uniforms: usize,
+    ) -> anyhow::Result<BindingLayout> {
+        let layout = self.layout(tensors, uniforms);
+        let segments = segment_count(self.bindings.iter().map(|&i| &tensors[i]));
+        layout
+            .check(segments, uniforms)
+            .map_err(|e| anyhow::anyhow!("Stage {}: {}", self.label, e))?;
+        self.check_roles(tensors, roles, &layout)?;
+        Ok(layout)
+    }
+
+    /// Checks the roles of the tensors this stage binds against the access modes its source declares.
+    pub fn check_roles(
+        &self,
+        tensors: &[CPUTensor],
+        roles: &[TensorRole],
+        layout: &BindingLayout,
+    ) -> anyhow::Result<()> {
+        let check = || {
+            let (module, _) = validate_module(&self.source)?;
+            let bound = self.bindings.iter().map(|&i| (i, (&tensors[i], roles[i])));
+            check_bound_roles(&module, bound, &layout.storage)
+        };
+        check().map_err(|e: anyhow::Error| anyhow::anyhow!("Stage {}: {}", self.label, e))
+    }
+
+    /// Checks the stage against the device limits before any pipeline is created:
+    /// its dispatch, the resources its source reaches, and the size of every buffer it binds.
+    pub fn check_limits(
+        &self,
+        tensors: &[CPUTensor],
+        limits: &wgpu::Limits,
+    ) -> anyhow::Result<ResourceUsage> {
+        let check = || {
+            self.workload.check_limits(limits)?;
+            let (module, info) = specialize(&self.source, &self.constants)?;
+            let usage = ResourceUsage::of(&module, &info, &self.entry_point)?;
+            usage.check(limits)?;
+            for &i in &self.bindings {
+                let tensor = &tensors[i];
+                let n_bytes = tensor.storage().n_bytes();
+                if n_bytes as u64 > limits.max_buffer_size {
+                    anyhow::bail!(
+                        "Tensor {} is {} bytes, the device allows {} per buffer",
+                        i,
+                        n_bytes,
+                        limits.max_buffer_size
+                    );
+                }
+                for segment in tensor.dt().segments(tensor.shape().numel(), n_bytes) {
+                    let size = segment.size.map_or(n_bytes as u64, |s| s.get());
+                    if size > limits.max_storage_buffer_binding_size as u64 {
+                        anyhow::bail!(
+                            "Tensor {} binds {} bytes, the device allows {} per storage binding",
+                            i,
+                            size,
+                            limits.max_storage_buffer_binding_size
+                        );
+                    }
+                }
+            }
+            Ok(usage)
+        };
+        check().map_err(|e: anyhow::Error| anyhow::anyhow!("Stage {}: {}", self.label, e))
+    }
+
+    /// The subset of `items` this stage binds, in binding order.
+    pub fn bound<T: Clone>(&self, items: &[T]) -> Vec<T> {
+        self.bindings.iter().map(|&i| items[i].clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{shape, wgc, wgs, CPUTensor, Stage, Workload};
+
+    #[test]
+    pub fn indirect_args_checked() {
+        let tensors = [
+            CPUTensor::zeros::<f32>(shape![4]),
+            CPUTensor::zeros::<u32>(shape![4]),
+        ];
+        let stage = |tensor, offset| {
+            let workload = Workload::new(wgs![64, 1, 1], wgc![1, 1, 1]);
+            Stage::new(
+                String::new(),
+                String::new(),
+                "main".into(),
+                workload,
+                vec![],
+            )
+            .with_indirect(tensor, offset)
+        };
+        assert!(stage(1, 4).check_indirect(&tensors).is_ok());
+        assert!(stage(0, 0).check_indirect(&tensors).is_err());
+        assert!(stage(1, 2).check_indirect(&tensors).is_err());
+        assert!(stage(1, 8).check_indirect(&tensors).is_err());
+        assert!(stage(2, 0).check_indirect(&tensors).is_err());
+    }
+}
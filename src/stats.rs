@@ -0,0 +1,149 @@
+/// Durations of the valid pairs in a range of resolved timestamps.
+#[derive(Debug, Clone, Default)]
+pub struct PairDurations {
+    pub durations: Vec<u64>,
+    pub dropped: usize,
+}
+
+impl PairDurations {
+    /// Fraction of invalid pairs above which a range is rejected outright.
+    pub const MAX_DROPPED_FRACTION: f64 = 0.1;
+    /// Longest span a pair may cover, in ticks. Hours at nanosecond ticks, still minutes at the
+    /// finest periods in use; longer spans come from garbage in uninitialised resolve slots.
+    pub const MAX_PAIR_TICKS: u64 = 1 << 44;
+
+    /// Some drivers produce zeroed, reordered, wrapped or garbage timestamps.
+    /// Such pairs are dropped instead of underflowing. A zero-length pair is kept.
+    pub fn from_timestamps(timestamps: &[u64]) -> anyhow::Result<Self> {
+        assert!(timestamps.len().is_multiple_of(2));
+        let mut durations = vec![];
+        let mut dropped = 0;
+        for pair in timestamps.chunks_exact(2) {
+            let (start, end) = (pair[0], pair[1]);
+            if start == 0 || end < start || end - start > Self::MAX_PAIR_TICKS {
+                log::trace!("Invalid timestamp pair: {} -> {}", start, end);
+                dropped += 1;
+            } else {
+                durations.push(end - start);
+            }
+        }
+        let total = durations.len() + dropped;
+        if dropped as f64 > total as f64 * Self::MAX_DROPPED_FRACTION {
+            anyhow::bail!(
+                "{} of {} timestamp pairs were zeroed, reordered, wrapped or out of range",
+                dropped,
+                total
+            );
+        }
+        Ok(Self { durations, dropped })
+    }
+
+    /// Sum of the valid durations, scaled up to stand in for the dropped pairs.
+    pub fn elapsed(&self) -> u64 {
+        if self.durations.is_empty() {
+            return 0;
+        }
+        let valid: u128 = self.durations.iter().map(|&d| d as u128).sum();
+        let total = (self.durations.len() + self.dropped) as u128;
+        (valid * total / self.durations.len() as u128) as u64
+    }
+}
+
+/// Summary of per-dispatch GPU latencies, in nanoseconds.
+#[derive(Debug, Clone)]
+pub struct DispatchStats {
+    pub samples: usize,
+    pub dropped: usize,
+    pub mean: f64,
+    pub min: f64,
+    pub median: f64,
+    pub p99: f64,
+}
+
+impl DispatchStats {
+    pub fn from_samples(mut samples: Vec<f64>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_by(|a, b| a.total_cmp(b));
+        //Nearest-rank percentile: the smallest sample with at least p of them at or below it
+        let percentile = |p: f64| {
+            let rank = (p * samples.len() as f64).ceil() as usize;
+            samples[rank.clamp(1, samples.len()) - 1]
+        };
+        Some(Self {
+            samples: samples.len(),
+            dropped: 0,
+            mean: samples.iter().sum::<f64>() / samples.len() as f64,
+            min: samples[0],
+            median: percentile(0.5),
+            p99: percentile(0.99),
+        })
+    }
+}
+
+impl std::fmt::Display for DispatchStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "per dispatch: [min {:.4} ns median {:.4} ns p99 {:.4} ns] mean {:.4} ns ({} samples)",
+            self.min, self.median, self.p99, self.mean, self.samples
+        )?;
+        if self.dropped > 0 {
+            write!(f, ", {} invalid pairs dropped", self.dropped)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DispatchStats, PairDurations};
+
+    #[test]
+    pub fn dispatch_percentiles() {
+        let samples = (1..=100).rev().map(|x| x as f64).collect();
+        let stats = DispatchStats::from_samples(samples).unwrap();
+        assert_eq!(stats.samples, 100);
+        assert_eq!(stats.min, 1.0);
+        assert_eq!(stats.median, 50.0);
+        assert_eq!(stats.p99, 99.0);
+        assert_eq!(stats.mean, 50.5);
+
+        let odd = DispatchStats::from_samples(vec![5.0, 1.0, 4.0, 2.0, 3.0]).unwrap();
+        assert_eq!((odd.median, odd.p99), (3.0, 5.0));
+        let single = DispatchStats::from_samples(vec![7.0]).unwrap();
+        assert_eq!((single.median, single.p99), (7.0, 7.0));
+        assert!(DispatchStats::from_samples(vec![]).is_none());
+    }
+
+    #[test]
+    pub fn invalid_pairs_dropped() {
+        let mut timestamps = vec![];
+        for i in 0..20u64 {
+            timestamps.extend([100 + i * 10, 105 + i * 10]);
+        }
+        timestamps[2] = 0; //zeroed
+        timestamps[5] = 100; //reordered
+        let pairs = PairDurations::from_timestamps(&timestamps).unwrap();
+        assert_eq!(pairs.dropped, 2);
+        assert_eq!(pairs.durations.len(), 18);
+        assert_eq!(pairs.elapsed(), 100);
+
+        timestamps[7] = 0;
+        timestamps[10] = u64::MAX; //wrapped
+        assert!(PairDurations::from_timestamps(&timestamps).is_err());
+
+        //Back to back timestamps are a real, if coarse, measurement
+        let zero = PairDurations::from_timestamps(&[100, 100, 200, 205]).unwrap();
+        assert_eq!((zero.durations.clone(), zero.dropped), (vec![0, 5], 0));
+        //Garbage in an uninitialised slot, far past its start
+        let mut garbage = vec![];
+        for i in 0..20u64 {
+            garbage.extend([1000 + i * 10, 1005 + i * 10]);
+        }
+        garbage[1] = 1000 + PairDurations::MAX_PAIR_TICKS + 1;
+        let pairs = PairDurations::from_timestamps(&garbage).unwrap();
+        assert_eq!((pairs.durations.len(), pairs.dropped), (19, 1));
+    }
+}
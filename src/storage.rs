@@ -1,5 +1,5 @@
 use std::{alloc::Layout, ops::RangeBounds, sync::Arc};
-use wgpu::{util::DeviceExt, Buffer, BufferAddress, BufferSlice, BufferUsages};
+use wgpu::{util::DeviceExt, BufferAddress, BufferSlice, BufferUsages};
 
 use crate::GPUHandle;
 
@@ -26,6 +26,28 @@ impl CPUStorage {
     pub fn as_bytes(&self) -> &[u8] {
         unsafe { std::slice::from_raw_parts(self.0, self.1.size()) }
     }
+
+    pub fn to_gpu_with_usage(self, handle: &GPUHandle, usage: BufferUsages) -> GPUStorage {
+        let mut min_bytes = [0; 16];
+        let bytes = if self.as_bytes().len() < 16 {
+            min_bytes[..self.as_bytes().len()].copy_from_slice(self.as_bytes());
+            &min_bytes //&[u8]
+        } else {
+            self.as_bytes() //&[u8]
+        };
+
+        let buffer = handle
+            .device()
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytes,
+                usage,
+            });
+        //These should be batched up
+        handle.queue().submit(None);
+        handle.device().poll(wgpu::Maintain::Wait);
+        GPUStorage(buffer.into())
+    }
 }
 
 impl Clone for CPUStorage {
@@ -49,25 +71,8 @@ impl Drop for CPUStorage {
 impl Storage for CPUStorage {
     //No allocations are pooled here because we don't care
     fn to_gpu(self, handle: &GPUHandle) -> GPUStorage {
-        let mut min_bytes = [0; 16];
-        let bytes = if self.as_bytes().len() < 16 {
-            min_bytes[..self.as_bytes().len()].copy_from_slice(self.as_bytes());
-            &min_bytes //&[u8]
-        } else {
-            self.as_bytes() //&[u8]
-        };
-
-        let buffer = handle
-            .device()
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: None,
-                contents: bytes,
-                usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
-            });
-        //These should be batched up
-        handle.queue().submit(None);
-        handle.device().poll(wgpu::Maintain::Wait);
-        GPUStorage(buffer.into())
+        let usage = BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC;
+        self.to_gpu_with_usage(handle, usage)
     }
 
     fn to_cpu(self) -> CPUStorage {
@@ -108,7 +113,7 @@ impl From<GPUBuffer> for GPUStorage {
 impl std::fmt::Debug for GPUStorage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("GPUStorage")
-            .field("buffer", &self.0.global_id())
+            .field("buffer", &*self.0)
             .field("size", &self.0.size())
             .field("usage", &self.0.usage())
             .finish()
@@ -117,7 +122,7 @@ impl std::fmt::Debug for GPUStorage {
 
 impl PartialEq for GPUStorage {
     fn eq(&self, other: &Self) -> bool {
-        self.0.global_id() == other.0.global_id()
+        *self.0 == *other.0
     }
 }
 
@@ -134,7 +139,7 @@ impl GPUStorage {
         self.0 = b;
     }
 
-    pub fn as_entire_binding(&self) -> wgpu::BindingResource {
+    pub fn as_entire_binding(&self) -> wgpu::BindingResource<'_> {
         self.0.as_entire_binding()
     }
 
@@ -142,7 +147,7 @@ impl GPUStorage {
         self.0.usage()
     }
 
-    pub fn slice<S: RangeBounds<wgpu::BufferAddress>>(&self, bounds: S) -> BufferSlice {
+    pub fn slice<S: RangeBounds<wgpu::BufferAddress>>(&self, bounds: S) -> BufferSlice<'_> {
         self.0.slice(bounds)
     }
 
@@ -150,10 +155,6 @@ impl GPUStorage {
         self.0.unmap();
     }
 
-    pub fn buffer_id(&self) -> wgpu::Id<Buffer> {
-        self.0.global_id()
-    }
-
     pub fn size(&self) -> BufferAddress {
         self.0.size()
     }
@@ -1,11 +1,15 @@
 use bytemuck::NoUninit;
 use ndarray::Dimension;
-use numpy::ndarray::{ArrayD, ArrayViewD};
+use ndarray::{ArrayD, ArrayViewD};
 use rand::{distributions::uniform::SampleUniform, prelude::SeedableRng, rngs::SmallRng};
 use rand_distr::{Distribution, Poisson};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
 
-use numpy::PyArrayDyn;
-use wgpu::{BindGroupEntry, BindingResource, BufferUsages};
+use wgpu::{BindingResource, BufferUsages};
 
 use crate::storage::{CPUStorage, GPUStorage};
 use crate::DType;
@@ -57,6 +61,8 @@ impl<S: Storage> Tensor<S> {
 pub type CPUTensor = Tensor<CPUStorage>;
 
 impl CPUTensor {
+    /// # Safety
+    /// The contents are uninitialized until written.
     pub unsafe fn uninitialized(dt: DType, shape: Shape, alignment: usize) -> anyhow::Result<Self> {
         let bytes = shape.numel() * dt.size_of();
         let layout = std::alloc::Layout::from_size_align(bytes, alignment)?;
@@ -86,6 +92,8 @@ impl CPUTensor {
         tensor
     }
 
+    /// # Safety
+    /// `data` must hold a tensor of `dt` and `shape` in its packed layout.
     pub unsafe fn from_quantized<T: DataType, U: AsRef<[T]>>(
         data: U,
         shape: Shape,
@@ -112,9 +120,12 @@ impl CPUTensor {
         tensor
     }
 
+    /// Seed used by `randn`, so the same shape always yields the same tensor.
+    pub const RANDN_SEED: u64 = 42;
+
     pub fn randn<T: num_traits::Float + DataType + SampleUniform>(shape: Shape) -> Self {
         let between = Poisson::new(11.0).unwrap();
-        let mut rng: SmallRng = SeedableRng::seed_from_u64(42);
+        let mut rng: SmallRng = SeedableRng::seed_from_u64(Self::RANDN_SEED);
         let rand_vec = (0..shape.numel())
             .map(|_| T::from(between.sample(&mut rng)).unwrap())
             .collect::<Vec<_>>();
@@ -131,11 +142,20 @@ impl CPUTensor {
         GPUTensor::new(self.dt, self.shape.clone(), storage)
     }
 
+    pub fn into_gpu_with_usage(self, handle: &GPUHandle, usage: BufferUsages) -> GPUTensor {
+        let storage = self.storage.to_gpu_with_usage(handle, usage);
+        GPUTensor::new(self.dt, self.shape.clone(), storage)
+    }
+
+    /// # Safety
+    /// `D` must match the tensor's dtype.
     pub unsafe fn into_array_unchecked<D: DataType>(self) -> ArrayD<D> {
         self.to_array_view_unchecked::<D>().to_owned()
     }
 
-    pub unsafe fn to_array_view_unchecked<T: DataType>(&self) -> ArrayViewD<T> {
+    /// # Safety
+    /// `T` must match the tensor's dtype.
+    pub unsafe fn to_array_view_unchecked<T: DataType>(&self) -> ArrayViewD<'_, T> {
         let inner = self.storage().inner();
         if self.n_bytes() != 0 {
             ArrayViewD::from_shape_ptr(self.shape().to_vec(), inner.0 as *const T)
@@ -144,12 +164,54 @@ impl CPUTensor {
         }
     }
 
-    pub fn to_py<'s, 'p: 's, T: DataType + numpy::Element>(
-        &'s self,
-        py: &'p pyo3::Python<'p>,
-    ) -> &PyArrayDyn<T> {
-        use numpy::PyArray;
-        PyArray::from_owned_array(*py, unsafe { self.clone().into_array_unchecked::<T>() })
+    /// Reads a C-ordered `.npy` file.
+    pub fn read_npy(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = BufReader::new(File::open(path.as_ref())?);
+        let npy = npyz::NpyFile::new(file)?;
+        if npy.order() != npyz::Order::C {
+            anyhow::bail!("{} is Fortran ordered", path.as_ref().display());
+        }
+        let shape = npy.shape().iter().map(|&d| d as usize).collect::<Vec<_>>();
+        let shape = Shape::from(shape.as_slice());
+        let dt = DType::try_from(npy.dtype())
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.as_ref().display(), e))?;
+        Ok(match dt {
+            DType::F32 => Self::from_slice(&npy.into_vec::<f32>()?, shape),
+            DType::I32 => Self::from_slice(&npy.into_vec::<i32>()?, shape),
+            DType::U32 => Self::from_slice(&npy.into_vec::<u32>()?, shape),
+            dt => anyhow::bail!("Unsupported npy dtype {:?}", dt),
+        })
+    }
+
+    /// Writes the tensor as a `.npy` file, only plain F32, I32 and U32 tensors have an equivalent.
+    pub fn write_npy(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        match self.dt() {
+            DType::F32 => self.write_npy_typed::<f32>(path.as_ref()),
+            DType::I32 => self.write_npy_typed::<i32>(path.as_ref()),
+            DType::U32 => self.write_npy_typed::<u32>(path.as_ref()),
+            dt => anyhow::bail!("Cannot write {:?} tensor as npy", dt),
+        }
+    }
+
+    fn write_npy_typed<T: DataType + npyz::AutoSerialize>(
+        &self,
+        path: &Path,
+    ) -> anyhow::Result<()> {
+        let shape = self
+            .shape()
+            .to_vec()
+            .iter()
+            .map(|&d| d as u64)
+            .collect::<Vec<_>>();
+        use npyz::WriterBuilder;
+        let mut writer = npyz::WriteOptions::new()
+            .default_dtype()
+            .shape(&shape)
+            .writer(BufWriter::new(File::create(path)?))
+            .begin_nd()?;
+        writer.extend(self.to_vec::<T>()?)?;
+        writer.finish()?;
+        Ok(())
     }
 
     pub fn fmt(&self) -> String {
@@ -232,18 +294,6 @@ impl std::fmt::Display for CPUTensor {
     }
 }
 
-impl<T: DataType + numpy::Element> From<&PyArrayDyn<T>> for CPUTensor {
-    fn from(array: &PyArrayDyn<T>) -> Self {
-        Self::from(array.to_owned_array())
-    }
-}
-
-impl<T: DataType + numpy::Element> From<PyArrayDyn<T>> for CPUTensor {
-    fn from(array: PyArrayDyn<T>) -> Self {
-        Self::from(array.to_owned_array())
-    }
-}
-
 impl<T: DataType> From<ArrayD<T>> for CPUTensor {
     fn from(it: ArrayD<T>) -> Self {
         if it.as_slice().is_some() {
@@ -258,7 +308,7 @@ impl<T: DataType> From<ArrayD<T>> for CPUTensor {
 
             Tensor::new(T::dt(), shape, CPUStorage::new(data, layout))
         } else {
-            panic!("Cannot convert numpy array with non-contiguous memory layout to tensor");
+            panic!("Cannot convert array with non-contiguous memory layout to tensor");
         }
     }
 }
@@ -269,27 +319,24 @@ impl GPUTensor {
     /// # Bindings
     ///
     /// Only applicable to GPU tensors.
-    /// Generates the bind group entries required to bind the tensor to a kernel.
-    /// Quantized tensors may use multiple bind groups.
-    /// Unquantized tensors should only use a single bind group.
-    pub(crate) fn bindings(&self, current_binding: usize) -> Vec<BindGroupEntry> {
+    /// Generates a binding resource per buffer segment, placed by the kernel's `BindingLayout`.
+    /// Quantized tensors may use multiple bindings.
+    /// Unquantized tensors should only use a single binding.
+    pub(crate) fn bindings(&self) -> Vec<BindingResource<'_>> {
         let buf = self.storage().inner();
         let numel = self.shape().numel();
         let segments = self.dt().segments(numel, buf.size() as usize);
 
-        let mut entries = vec![];
-        for (idx, seg) in segments.iter().enumerate() {
-            let (offset, size) = (seg.offset, seg.size);
-            entries.push(BindGroupEntry {
-                binding: ((current_binding + idx) % 4) as _,
-                resource: BindingResource::Buffer(wgpu::BufferBinding {
+        segments
+            .iter()
+            .map(|seg| {
+                BindingResource::Buffer(wgpu::BufferBinding {
                     buffer: buf,
-                    offset,
-                    size,
-                }),
-            });
-        }
-        entries
+                    offset: seg.offset,
+                    size: seg.size,
+                })
+            })
+            .collect()
     }
 
     fn read_to_host<A: NoUninit>(shape: Shape, dt: DType, bytes: &[A]) -> CPUTensor {
@@ -0,0 +1,206 @@
+use criterion::{measurement::ValueFormatter, Throughput};
+
+use crate::{CPUTensor, Storage};
+
+/// Work done by one dispatch, from which throughput is derived.
+///
+/// Criterion only knows about bytes and elements, so FLOPs are handed to it as
+/// elements and the timer is told to format them as FLOP/s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KernelThroughput {
+    Bytes(u64),
+    BytesDecimal(u64),
+    Elements(u64),
+    Flops(u64),
+}
+
+impl KernelThroughput {
+    pub fn split(self) -> (Throughput, ElementKind) {
+        match self {
+            KernelThroughput::Bytes(b) => (Throughput::Bytes(b), ElementKind::Elements),
+            KernelThroughput::BytesDecimal(b) => {
+                (Throughput::BytesDecimal(b), ElementKind::Elements)
+            }
+            KernelThroughput::Elements(e) => (Throughput::Elements(e), ElementKind::Elements),
+            KernelThroughput::Flops(f) => (Throughput::Elements(f), ElementKind::Flops),
+        }
+    }
+}
+
+impl From<Throughput> for KernelThroughput {
+    fn from(throughput: Throughput) -> Self {
+        match throughput {
+            Throughput::Bytes(b) => KernelThroughput::Bytes(b),
+            Throughput::BytesDecimal(b) => KernelThroughput::BytesDecimal(b),
+            Throughput::Elements(e) => KernelThroughput::Elements(e),
+        }
+    }
+}
+
+/// Bytes moved and FLOPs performed by a single dispatch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KernelWork {
+    pub bytes: Option<u64>,
+    pub flops: Option<u64>,
+}
+
+impl KernelWork {
+    /// Assumes every tensor is read or written exactly once, with no FLOPs declared.
+    pub fn from_tensors(tensors: &[CPUTensor]) -> Self {
+        let bytes = tensors.iter().map(|t| t.storage().n_bytes() as u64).sum();
+        Self {
+            bytes: Some(bytes),
+            flops: None,
+        }
+    }
+
+    /// `from_tensors`, with `flops` FLOPs for every element of the first tensor, the kernel's input.
+    pub fn per_element(tensors: &[CPUTensor], flops: u64) -> Self {
+        Self {
+            flops: Some(tensors[0].shape().numel() as u64 * flops),
+            ..Self::from_tensors(tensors)
+        }
+    }
+
+    /// Criterion can only plot one throughput, FLOPs are preferred when declared.
+    pub fn throughput(&self) -> Option<KernelThroughput> {
+        match (self.bytes, self.flops) {
+            (_, Some(f)) => Some(KernelThroughput::Flops(f)),
+            (Some(b), None) => Some(KernelThroughput::Bytes(b)),
+            (None, None) => None,
+        }
+    }
+}
+
+/// FLOPs per element of a LayerNorm: mean, variance, normalize, scale & shift.
+pub const LAYERNORM_FLOPS: u64 = 8;
+
+/// FLOPs per element of a two pass softmax: max, shift & exp, sum, then shift, exp & scale again.
+pub const SOFTMAX_FLOPS: u64 = 7;
+
+/// What a criterion `Throughput::Elements` count represents.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ElementKind {
+    #[default]
+    Elements,
+    Flops,
+}
+
+const TIME_UNITS: [&str; 4] = ["ns", "µs", "ms", "s"];
+const BINARY_BYTE_UNITS: [&str; 5] = ["B/s", "KiB/s", "MiB/s", "GiB/s", "TiB/s"];
+const DECIMAL_BYTE_UNITS: [&str; 5] = ["B/s", "KB/s", "MB/s", "GB/s", "TB/s"];
+const ELEMENT_UNITS: [&str; 5] = ["elem/s", "Kelem/s", "Melem/s", "Gelem/s", "Telem/s"];
+const FLOP_UNITS: [&str; 5] = ["FLOP/s", "KFLOP/s", "MFLOP/s", "GFLOP/s", "TFLOP/s"];
+
+/// Picks the largest unit that keeps `typical` at or above 1.
+fn pick_unit(typical: f64, base: f64, units: &[&'static str]) -> (f64, &'static str) {
+    let mut denominator = 1.0;
+    let mut idx = 0;
+    while idx + 1 < units.len() && typical >= denominator * base {
+        denominator *= base;
+        idx += 1;
+    }
+    (denominator, units[idx])
+}
+
+pub(crate) struct WgpuTimerFormatter {
+    pub(crate) elements: ElementKind,
+}
+
+impl WgpuTimerFormatter {
+    fn scale_rate(
+        count: f64,
+        typical: f64,
+        values: &mut [f64],
+        base: f64,
+        units: &[&'static str],
+    ) -> &'static str {
+        let (denominator, unit) = pick_unit(count * (1e9 / typical), base, units);
+        for val in values {
+            *val = count * (1e9 / *val) / denominator;
+        }
+        unit
+    }
+}
+
+impl ValueFormatter for WgpuTimerFormatter {
+    fn format_value(&self, value: f64) -> String {
+        let mut values = [value];
+        let unit = self.scale_values(value, &mut values);
+        format!("{:.4} {}", values[0], unit)
+    }
+
+    fn format_throughput(&self, throughput: &Throughput, value: f64) -> String {
+        let mut values = [value];
+        let unit = self.scale_throughputs(value, throughput, &mut values);
+        format!("{:.4} {}", values[0], unit)
+    }
+
+    fn scale_values(&self, typical_value: f64, values: &mut [f64]) -> &'static str {
+        let (denominator, unit) = pick_unit(typical_value, 1000.0, &TIME_UNITS);
+        for val in values {
+            *val /= denominator;
+        }
+        unit
+    }
+
+    fn scale_throughputs(
+        &self,
+        typical_value: f64,
+        throughput: &Throughput,
+        values: &mut [f64],
+    ) -> &'static str {
+        match (throughput, self.elements) {
+            (Throughput::Bytes(b), _) => {
+                Self::scale_rate(*b as f64, typical_value, values, 1024.0, &BINARY_BYTE_UNITS)
+            }
+            (Throughput::BytesDecimal(b), _) => Self::scale_rate(
+                *b as f64,
+                typical_value,
+                values,
+                1000.0,
+                &DECIMAL_BYTE_UNITS,
+            ),
+            (Throughput::Elements(e), ElementKind::Elements) => {
+                Self::scale_rate(*e as f64, typical_value, values, 1000.0, &ELEMENT_UNITS)
+            }
+            (Throughput::Elements(f), ElementKind::Flops) => {
+                Self::scale_rate(*f as f64, typical_value, values, 1000.0, &FLOP_UNITS)
+            }
+        }
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        "ns"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use criterion::{measurement::ValueFormatter, Throughput};
+
+    use crate::{ElementKind, WgpuTimerFormatter};
+
+    #[test]
+    pub fn scales_units() {
+        let elements = WgpuTimerFormatter {
+            elements: ElementKind::Elements,
+        };
+        let flops = WgpuTimerFormatter {
+            elements: ElementKind::Flops,
+        };
+        assert_eq!(elements.format_value(512.0), "512.0000 ns");
+        assert_eq!(elements.format_value(48_831.5), "48.8315 µs");
+        assert_eq!(elements.format_value(2_500_000.0), "2.5000 ms");
+
+        let gib = Throughput::Bytes(1 << 30);
+        assert_eq!(elements.format_throughput(&gib, 1e9), "1.0000 GiB/s");
+        let mb = Throughput::BytesDecimal(5_000_000);
+        assert_eq!(elements.format_throughput(&mb, 1e9), "5.0000 MB/s");
+
+        let count = Throughput::Elements(2 * 2048 * 2048 * 2048);
+        assert_eq!(elements.format_throughput(&count, 1e9), "17.1799 Gelem/s");
+        assert_eq!(flops.format_throughput(&count, 1e9), "17.1799 GFLOP/s");
+        assert_eq!(flops.format_throughput(&count, 1e6), "17.1799 TFLOP/s");
+    }
+}
@@ -0,0 +1,240 @@
+use std::{
+    collections::BTreeMap,
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    prepare, time_stages, validate_entry_point, BenchConfig, DispatchStats, GPUHandle, KernelBench,
+    KernelTensor, WgpuTimer,
+};
+
+/// A template parameter and the values the autotuner may try for it.
+#[derive(Debug, Clone, derive_new::new)]
+pub struct TuneParam {
+    pub name: &'static str,
+    pub values: Vec<u32>,
+}
+
+/// One point of the search space, from parameter name to value.
+pub type TuneConfig = BTreeMap<String, u32>;
+
+/// A kernel whose template parameters can be searched by the `Autotuner`.
+pub trait Tunable: KernelBench + Sized {
+    /// Candidate values of each parameter on the device behind `handle`.
+    fn tunables(handle: &GPUHandle) -> Vec<TuneParam>;
+
+    /// The same problem, rendered with the given template parameters.
+    fn with_config(&self, config: &TuneConfig) -> Self;
+
+    /// Identifies the problem independently of the template parameters.
+    /// Defaults to the shapes of the kernel's tensors.
+    fn problem(&self) -> String {
+        self.tensors()
+            .iter()
+            .map(|t| format!("{:?}", t.tensor.shape()))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Every combination of the parameters' values.
+pub fn search_space(params: &[TuneParam]) -> Vec<TuneConfig> {
+    params
+        .iter()
+        .fold(vec![TuneConfig::new()], |configs, param| {
+            configs
+                .iter()
+                .flat_map(|config| {
+                    param.values.iter().map(move |&value| {
+                        let mut config = config.clone();
+                        config.insert(param.name.to_string(), value);
+                        config
+                    })
+                })
+                .collect()
+        })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TuneEntry {
+    pub config: TuneConfig,
+    pub median_ns: f64,
+}
+
+/// # TuneCache
+///
+/// Best known configuration per adapter, kernel and problem, persisted as JSON.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TuneCache {
+    entries: BTreeMap<String, TuneEntry>,
+}
+
+impl TuneCache {
+    pub const ENV_VAR: &'static str = "WGPU_BENCH_TUNE_CACHE";
+    pub const DEFAULT_PATH: &'static str = "target/autotune.json";
+
+    /// Cache location, `WGPU_BENCH_TUNE_CACHE` if set.
+    pub fn path() -> PathBuf {
+        std::env::var(Self::ENV_VAR)
+            .unwrap_or_else(|_| Self::DEFAULT_PATH.to_string())
+            .into()
+    }
+
+    /// Loads the cache, starting empty if the file doesn't exist yet.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = std::fs::File::create(path)?;
+        Ok(serde_json::to_writer_pretty(file, self)?)
+    }
+
+    pub fn key(adapter: &wgpu::AdapterInfo, kernel: &str, problem: &str) -> String {
+        format!(
+            "{} ({:?}, {})/{}/{}",
+            adapter.name, adapter.backend, adapter.driver, kernel, problem
+        )
+    }
+
+    pub fn get(&self, key: &str) -> Option<&TuneEntry> {
+        self.entries.get(key)
+    }
+
+    pub fn insert(&mut self, key: String, entry: TuneEntry) {
+        self.entries.insert(key, entry);
+    }
+}
+
+/// # Autotuner
+///
+/// Exhaustively searches a `Tunable` kernel's template parameters,
+/// discarding configurations that exceed device limits, fail to compile or fail validation.
+/// The fastest by median dispatch time is cached for later runs.
+pub struct Autotuner<'a> {
+    timer: &'a WgpuTimer,
+    pub config: BenchConfig,
+    /// Timed iterations per candidate.
+    pub iterations: usize,
+    pub cache_path: PathBuf,
+}
+
+impl<'a> Autotuner<'a> {
+    pub const ITERATIONS: usize = 10;
+
+    pub fn new(timer: &'a WgpuTimer) -> Self {
+        Self {
+            timer,
+            config: BenchConfig {
+                dispatches_per_pass: 10,
+                ..Default::default()
+            },
+            iterations: Self::ITERATIONS,
+            cache_path: TuneCache::path(),
+        }
+    }
+
+    /// Returns the kernel rendered with its best configuration, searching only on a cache miss.
+    pub fn tune<K: Tunable>(&self, kernel: &K) -> anyhow::Result<K> {
+        let mut cache = TuneCache::load(&self.cache_path)?;
+        let adapter = self.timer.handle().adapter_info();
+        let key = TuneCache::key(adapter, K::name(), &kernel.problem());
+        if let Some(entry) = cache.get(&key) {
+            log::info!("{}: cached config {:?}", key, entry.config);
+            return Ok(kernel.with_config(&entry.config));
+        }
+
+        let mut best: Option<TuneEntry> = None;
+        for config in search_space(&K::tunables(self.timer.handle())) {
+            let candidate = kernel.with_config(&config);
+            match self.evaluate(&candidate) {
+                Ok(stats) => {
+                    log::info!("{}: {:?} took {:.4} ns", key, config, stats.median);
+                    if !matches!(&best, Some(b) if b.median_ns <= stats.median) {
+                        best = Some(TuneEntry {
+                            config,
+                            median_ns: stats.median,
+                        });
+                    }
+                }
+                Err(e) => log::warn!("{}: dropping {:?}: {}", key, config, e),
+            }
+        }
+        let best = best.ok_or_else(|| anyhow::anyhow!("No valid configuration for {}", key))?;
+        println!(
+            "{}: tuned to {:?} ({:.4} ns)",
+            key, best.config, best.median_ns
+        );
+        let tuned = kernel.with_config(&best.config);
+        cache.insert(key, best);
+        cache.save(&self.cache_path)?;
+        Ok(tuned)
+    }
+
+    fn evaluate<K: KernelBench>(&self, kernel: &K) -> anyhow::Result<DispatchStats> {
+        self.config.check()?;
+        let handle = self.timer.handle();
+        let (tensors, roles) = KernelTensor::split(kernel.tensors());
+        let stages = kernel.stages(&tensors);
+        let uniforms = kernel.uniforms(handle, &tensors).len();
+        for stage in &stages {
+            stage.check_limits(&tensors, &handle.device().limits())?;
+            stage.checked_layout(&tensors, &roles, uniforms)?;
+            validate_entry_point(&stage.source, &stage.entry_point)?;
+        }
+
+        //Surface compilation and resource errors instead of the default panic,
+        //the stages prepared here are the ones timed
+        handle
+            .device()
+            .push_error_scope(wgpu::ErrorFilter::Validation);
+        let (prepared, _gpu_tensors) = prepare(
+            handle,
+            kernel,
+            tensors.clone(),
+            &roles,
+            self.config.dispatch,
+        );
+        if let Some(e) = pollster::block_on(handle.device().pop_error_scope()) {
+            anyhow::bail!("Pipeline creation failed: {}", e);
+        }
+
+        std::panic::catch_unwind(AssertUnwindSafe(|| {
+            kernel.validate(handle, &tensors, &roles)
+        }))
+        .map_err(|_| anyhow::anyhow!("Validation failed"))?;
+        time_stages(self.timer, &prepared, &self.config, self.iterations)?
+            .ok_or_else(|| anyhow::anyhow!("No samples recorded for {}", K::name()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{search_space, TuneParam};
+
+    #[test]
+    pub fn search_space_product() {
+        let params = vec![
+            TuneParam::new("TILE_DIM", vec![16, 32, 64]),
+            TuneParam::new("ROW_PER_THREAD", vec![4, 8]),
+        ];
+        let space = search_space(&params);
+        assert_eq!(space.len(), 6);
+        assert!(space
+            .iter()
+            .any(|c| c["TILE_DIM"] == 64 && c["ROW_PER_THREAD"] == 4));
+        assert_eq!(search_space(&[]).len(), 1);
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::{HashMap, HashSet};
+
+use naga::{
+    back::pipeline_constants::process_overrides,
+    valid::{Capabilities, ModuleInfo, ValidationFlags, Validator},
+};
+
+use crate::{KernelBench, KernelTensor, ShaderDump};
+
+/// # WgslError
+///
+/// A rendered kernel source that naga rejected.
+/// Displays the diagnostic with its spans, the tera context if known, and the numbered source.
+#[derive(Debug)]
+pub struct WgslError {
+    /// Diagnostic rendered against the source, pointing at the offending spans.
+    pub report: String,
+    /// 1-based line and column of the primary span.
+    pub location: Option<(u32, u32)>,
+    pub source: String,
+    /// Context the source was rendered from, see `render_wgsl`.
+    pub context: Option<tera::Context>,
+}
+
+impl WgslError {
+    fn new(source: &str, report: String, location: Option<naga::SourceLocation>) -> Self {
+        Self {
+            report,
+            location: location.map(|l| (l.line_number, l.line_position)),
+            source: source.to_string(),
+            context: None,
+        }
+    }
+
+    pub fn with_context(mut self, context: tera::Context) -> Self {
+        self.context = Some(context);
+        self
+    }
+}
+
+impl std::fmt::Display for WgslError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.report)?;
+        if let Some((line, column)) = self.location {
+            writeln!(f, "at {}:{}", line, column)?;
+        }
+        if let Some(context) = &self.context {
+            writeln!(f, "rendered from context: {}", context.clone().into_json())?;
+        }
+        for (i, line) in self.source.lines().enumerate() {
+            writeln!(f, "{:>4} | {}", i + 1, line)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for WgslError {}
+
+/// Parses and validates WGSL with naga, no GPU required.
+/// Every capability is allowed, so device-specific features are left for pipeline creation to reject.
+pub fn validate_wgsl(source: &str) -> Result<naga::Module, WgslError> {
+    validate_module(source).map(|(module, _)| module)
+}
+
+/// As `validate_wgsl`, also returning the validator's analysis the naga backends need.
+pub fn validate_module(source: &str) -> Result<(naga::Module, ModuleInfo), WgslError> {
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|e| WgslError::new(source, e.emit_to_string(source), e.location(source)))?;
+    let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(&module)
+        .map_err(|e| WgslError::new(source, e.emit_to_string(source), e.location(source)))?;
+    Ok((module, info))
+}
+
+/// As `validate_wgsl`, also checking the module has a compute entry point named `entry_point`.
+pub fn validate_entry_point(source: &str, entry_point: &str) -> Result<naga::Module, WgslError> {
+    let module = validate_wgsl(source)?;
+    let found = module
+        .entry_points
+        .iter()
+        .any(|ep| ep.name == entry_point && ep.stage == naga::ShaderStage::Compute);
+    if !found {
+        let names = module
+            .entry_points
+            .iter()
+            .map(|ep| ep.name.as_str())
+            .collect::<Vec<_>>();
+        let report = format!(
+            "No compute entry point `{}`, found {:?}",
+            entry_point, names
+        );
+        return Err(WgslError::new(source, report, None));
+    }
+    Ok(module)
+}
+
+/// Validates `source` and applies `constants` to its `override` declarations with naga's pipeline
+/// constant pass, as wgpu does when it creates the pipeline, for reflection and translation.
+/// Each override takes its value from `constants`, keyed by its `@id` if it has one and by name otherwise,
+/// or else keeps its default.
+pub fn specialize(
+    source: &str,
+    constants: &HashMap<String, f64>,
+) -> anyhow::Result<(naga::Module, ModuleInfo)> {
+    let (module, info) = validate_module(source)?;
+    //wgpu ignores the name of an override with an id
+    let keys = module
+        .overrides
+        .iter()
+        .filter_map(|(_, o)| o.id.map(|id| id.to_string()).or_else(|| o.name.clone()))
+        .collect::<HashSet<_>>();
+    if let Some(unknown) = constants.keys().find(|k| !keys.contains(*k)) {
+        match module
+            .overrides
+            .iter()
+            .find(|(_, o)| o.name.as_ref() == Some(unknown))
+        {
+            Some((_, o)) => anyhow::bail!(
+                "Override `{}` has @id({}), set it by its id",
+                unknown,
+                o.id.unwrap()
+            ),
+            None => anyhow::bail!("No override `{}` in the source", unknown),
+        }
+    }
+    let (module, info) = process_overrides(&module, &info, constants)?;
+    Ok((module.into_owned(), info.into_owned()))
+}
+
+/// WGSL snippets and tera macros shipped with the crate, registered under `lib/` by `kernel_tera`.
+pub const WGSL_LIBRARY: &[(&str, &str)] = &[
+    ("lib/gemm.wgsl", include_str!("../kernels/lib/gemm.wgsl")),
+    (
+        "lib/reduce.wgsl",
+        include_str!("../kernels/lib/reduce.wgsl"),
+    ),
+    (
+        "lib/subgroup.wgsl",
+        include_str!("../kernels/lib/subgroup.wgsl"),
+    ),
+    (
+        "lib/welford.wgsl",
+        include_str!("../kernels/lib/welford.wgsl"),
+    ),
+    (
+        "lib/workgroup.wgsl",
+        include_str!("../kernels/lib/workgroup.wgsl"),
+    ),
+];
+
+/// A tera instance holding `WGSL_LIBRARY`. Kernel templates added to it can
+/// `{% include "lib/reduce.wgsl" %}` or `{% import "lib/gemm.wgsl" as gemm %}`.
+pub fn kernel_tera() -> tera::Tera {
+    let mut tera = tera::Tera::default();
+    tera.add_raw_templates(WGSL_LIBRARY.iter().copied())
+        .expect("WGSL library templates parse");
+    tera
+}
+
+/// Renders a template and validates the result, errors carry the context it was rendered from.
+/// A rejected render is also written to the dump, see `ShaderDump::capture`.
+pub fn render_wgsl(
+    tera: &tera::Tera,
+    template: &str,
+    context: &tera::Context,
+) -> anyhow::Result<String> {
+    let source = tera.render(template, context)?;
+    if let Err(e) = validate_wgsl(&source) {
+        ShaderDump::write_rejected(template, &source, context);
+        return Err(e.with_context(context.clone()).into());
+    }
+    Ok(source)
+}
+
+/// Renders and validates every stage of the kernel, checks its workloads against the portable limits
+/// and its tensors' roles and indirect args, without a GPU adapter.
+/// Assumes the kernel binds a single uniform unless its stages set a layout.
+pub fn check_kernel_sources<K: KernelBench>(kernel: &K) -> anyhow::Result<()> {
+    let (tensors, roles) = KernelTensor::split(kernel.tensors());
+    for stage in kernel.stages(&tensors) {
+        validate_entry_point(&stage.source, &stage.entry_point)
+            .map_err(|e| anyhow::anyhow!("{} stage {}: {}", K::name(), stage.label, e))?;
+        stage.specialized_module()?;
+        stage
+            .workload
+            .check()
+            .map_err(|e| anyhow::anyhow!("{} stage {}: {}", K::name(), stage.label, e))?;
+        stage.check_indirect(&tensors)?;
+        stage.checked_layout(&tensors, &roles, 1)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{kernel_tera, render_wgsl, specialize, validate_entry_point, validate_wgsl};
+
+    const SOURCE: &str = r#"
+@group(0) @binding(0)
+var<storage, read_write> Y: array<f32>;
+
+@compute @workgroup_size(64, 1, 1)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    Y[global_id.x] = 1f;
+}
+"#;
+
+    #[test]
+    pub fn wgsl_errors_located() {
+        assert!(validate_entry_point(SOURCE, "main").is_ok());
+        assert!(validate_entry_point(SOURCE, "other").is_err());
+
+        let broken = SOURCE.replace("1f;", "undefined;");
+        let error = validate_wgsl(&broken).unwrap_err();
+        assert_eq!(error.location.map(|(line, _)| line), Some(7));
+        assert!(error.to_string().contains("undefined"));
+    }
+
+    #[test]
+    pub fn overrides_specialized() {
+        let source = r#"
+@id(0)
+override BLOCK_SIZE: u32 = 64u;
+override EPS: f32 = 1e-5f; //variance epsilon
+override SCALE
+    : f32 = 2.0;
+//override UNUSED: u32 = 1u;
+
+@group(0) @binding(0)
+var<storage, read_write> Y: array<f32>;
+
+var<workgroup> smem: array<f32, BLOCK_SIZE>;
+
+@compute @workgroup_size(BLOCK_SIZE, 1, 1)
+fn main(@builtin(local_invocation_id) local_id: vec3<u32>) {
+    smem[local_id.x] = EPS * SCALE;
+    workgroupBarrier();
+    Y[local_id.x] = smem[BLOCK_SIZE - 1u - local_id.x];
+}
+"#;
+        let workgroup_size = |constants: &[(&str, f64)]| {
+            let constants = constants
+                .iter()
+                .map(|&(k, v)| (k.to_string(), v))
+                .collect::<HashMap<_, _>>();
+            specialize(source, &constants).map(|(module, _)| module.entry_points[0].workgroup_size)
+        };
+        assert_eq!(workgroup_size(&[]).unwrap(), [64, 1, 1]);
+        assert_eq!(
+            workgroup_size(&[("0", 256.), ("SCALE", 0.5)]).unwrap(),
+            [256, 1, 1]
+        );
+        //Keyed by its id only, as in wgpu
+        assert!(workgroup_size(&[("BLOCK_SIZE", 32.)]).is_err());
+        //Truncated, as wgpu does
+        assert_eq!(workgroup_size(&[("0", 16.5)]).unwrap(), [16, 1, 1]);
+        assert!(workgroup_size(&[("0", -1.)]).is_err());
+        assert!(workgroup_size(&[("UNUSED", 16.)]).is_err());
+    }
+
+    #[test]
+    pub fn library_snippets_render() {
+        let template = r#"{% import "lib/gemm.wgsl" as gemm %}
+struct Meta {
+    aStrides: vec3<i32>,
+    bStrides: vec3<i32>,
+    outStrides: vec3<i32>,
+}
+
+@group(0) @binding(0) var<storage, read> A: array<vec4<f32>>;
+@group(0) @binding(1) var<storage, read> B: array<u32>;
+@group(0) @binding(2) var<storage, read> absmax: array<f32>;
+@group(0) @binding(3) var<storage, read_write> result: array<vec4<f32>>;
+@group(1) @binding(0) var<uniform> metadata: Meta;
+
+var<workgroup> smem: array<f32, 64>;
+
+{{ gemm::indexing(out_strides="outStrides") }}
+{{ gemm::output(elem="vec4<f32>", width=4) }}
+{{ gemm::load(operand="A", elem="vec4<f32>", width=4) }}
+{{ gemm::load_sint8(operand="B", absmax="absmax", group_size=16) }}
+{% include "lib/reduce.wgsl" %}
+{% include "lib/welford.wgsl" %}
+
+@compute @workgroup_size(64, 1, 1)
+fn main(@builtin(local_invocation_id) local_id: vec3<u32>) {
+    smem[local_id.x] = getA(0, 0, i32(local_id.x)).x;
+    block_sum(local_id.x, 32u);
+    setOutputAtCoords(0, 0, 0, getB(0, 0, 0) * getAbsMax(0, 0, 0) + smem[0]);
+}
+"#;
+        let mut tera = kernel_tera();
+        tera.add_raw_template("library", template).unwrap();
+        render_wgsl(&tera, "library", &tera::Context::new()).unwrap();
+    }
+}
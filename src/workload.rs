@@ -1,10 +1,16 @@
-#[derive(Debug, derive_new::new)]
+use crate::Shape;
+
+#[derive(Debug, Clone, derive_new::new)]
 pub struct WorkgroupCount(pub u32, pub u32, pub u32); //Analagous to gridDim in CUDA
 
 impl WorkgroupCount {
     pub fn as_tuple(&self) -> (u32, u32, u32) {
         (self.0, self.1, self.2)
     }
+
+    pub fn total(&self) -> u64 {
+        self.0 as u64 * self.1 as u64 * self.2 as u64
+    }
 }
 
 #[macro_export]
@@ -14,7 +20,7 @@ macro_rules! wgc {
     };
 }
 
-#[derive(Debug, derive_new::new)]
+#[derive(Debug, Clone, derive_new::new)]
 pub struct WorkgroupSize(pub u32, pub u32, pub u32); //Analagous to blockDim in CUDA
 
 impl WorkgroupSize {
@@ -32,26 +38,253 @@ macro_rules! wgs {
 
 ///The Workload represents the entire piece of work.
 ///For more read: https://surma.dev/things/webgpu/
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Workload {
     size: WorkgroupSize,
     count: WorkgroupCount,
+    requested: WorkgroupCount,
+    foldable: bool,
+    coverage: Option<Coverage>,
 }
 
 impl Workload {
     pub fn new(size: WorkgroupSize, count: WorkgroupCount) -> Self {
-        Self { size, count }
+        Self {
+            size,
+            count: count.clone(),
+            requested: count,
+            foldable: false,
+            coverage: None,
+        }
+    }
+
+    /// As `new`, a 1D count over `MAX_COMPUTE_WORKGROUPS_PER_DIMENSION` folded into 2D or 3D, see `fold`.
+    /// Only for kernels that rebuild their row index with `linear_workgroup_id`.
+    /// Refolded against the device's own limit when the kernel is prepared, see `folded_to`,
+    /// so shaders must take the count from `num_workgroups` rather than the rendered context.
+    pub fn folded(size: WorkgroupSize, count: WorkgroupCount) -> Self {
+        let max = Self::MAX_COMPUTE_WORKGROUPS_PER_DIMENSION as u32;
+        Self {
+            count: Self::fold(&count, max),
+            foldable: true,
+            ..Self::new(size, count)
+        }
+    }
+
+    /// The requested count folded for a device allowing `max_per_dim` workgroups per dimension,
+    /// unchanged unless built with `folded`.
+    pub fn folded_to(&self, max_per_dim: u32) -> Self {
+        Self {
+            count: match self.foldable {
+                true => Self::fold(&self.requested, max_per_dim),
+                false => self.count.clone(),
+            },
+            ..self.clone()
+        }
+    }
+
+    /// Works out the count from the problem shape, see `WorkloadBuilder`.
+    pub fn builder(shape: &Shape, size: WorkgroupSize) -> WorkloadBuilder {
+        WorkloadBuilder::new(shape.to_vec(), size)
     }
 
+    /// The count dispatched, after folding.
     pub fn count(&self) -> &WorkgroupCount {
         &self.count
     }
 
+    /// The count asked for, before folding.
+    pub fn requested(&self) -> &WorkgroupCount {
+        &self.requested
+    }
+
+    pub fn is_folded(&self) -> bool {
+        self.count.as_tuple() != self.requested.as_tuple()
+    }
+
+    /// How the problem covers the dispatch, if the workload came from a `WorkloadBuilder`.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
     pub fn size(&self) -> &WorkgroupSize {
         &self.size
     }
 }
 
+/// # WorkloadBuilder
+///
+/// Derives the workgroup count from a logical problem shape, the workgroup size and how many
+/// elements each invocation handles along x (columns) and y (rows), for one of three mappings:
+/// - `linear`: the flattened problem spread across a 1D grid.
+/// - `rows`: one workgroup per row of the innermost dimension, its invocations striding along it.
+/// - `tiled`: a workgroup per 2D tile of the last two dimensions, leading dimensions along z.
+///
+/// e.g a GEMM computing 4 columns and `ROW_PER_THREAD` rows per invocation:
+/// `Workload::builder(&shape![B, M, N], wgs![TILE_DIM / 4, TILE_DIM / ROW_PER_THREAD, 1])
+/// .elements_per_invocation(4, ROW_PER_THREAD).tiled()`
+#[derive(Debug, Clone)]
+pub struct WorkloadBuilder {
+    shape: Vec<usize>,
+    size: WorkgroupSize,
+    elements: (usize, usize),
+    foldable: bool,
+}
+
+impl WorkloadBuilder {
+    pub fn new(shape: Vec<usize>, size: WorkgroupSize) -> Self {
+        Self {
+            shape,
+            size,
+            elements: (1, 1),
+            foldable: false,
+        }
+    }
+
+    /// Elements each invocation handles along x and y, e.g 4 for `vec4` loads. Defaults to 1.
+    pub fn elements_per_invocation(mut self, x: usize, y: usize) -> Self {
+        self.elements = (x.max(1), y.max(1));
+        self
+    }
+
+    /// Builds the workload with `Workload::folded`, for kernels indexing with `linear_workgroup_id`.
+    pub fn foldable(mut self) -> Self {
+        self.foldable = true;
+        self
+    }
+
+    fn numel(&self) -> usize {
+        self.shape.iter().product()
+    }
+
+    /// Innermost dimension, 1 for a scalar problem.
+    fn column_count(&self) -> usize {
+        self.shape.last().copied().unwrap_or(1)
+    }
+
+    /// Second innermost dimension, 1 below rank 2.
+    fn row_count(&self) -> usize {
+        self.shape.iter().rev().nth(1).copied().unwrap_or(1)
+    }
+
+    fn invocations_per_group(&self) -> usize {
+        self.size.total() as usize
+    }
+
+    fn finish(self, count: WorkgroupCount, coverage: Coverage) -> Workload {
+        let workload = if self.foldable {
+            Workload::folded(self.size, count)
+        } else {
+            Workload::new(self.size, count)
+        };
+        Workload {
+            coverage: Some(coverage),
+            ..workload
+        }
+    }
+
+    /// A 1D grid over the flattened problem, `elements_per_invocation(x, _)` consecutive elements each.
+    pub fn linear(self) -> Workload {
+        let numel = self.numel();
+        let per_group = self.invocations_per_group() * self.elements.0;
+        let groups = numel.div_ceil(per_group);
+        let coverage = Coverage {
+            elements: numel,
+            groups,
+            invocations: groups * self.invocations_per_group(),
+            busy_invocations: numel.div_ceil(self.elements.0),
+            tail_groups: !numel.is_multiple_of(per_group) as usize,
+        };
+        self.finish(WorkgroupCount::new(groups as _, 1, 1), coverage)
+    }
+
+    /// A workgroup per row, each invocation striding along it by the workgroup's span.
+    /// Rows whose length isn't a multiple of the span end on a partial pass, counted as tail groups.
+    pub fn rows(self) -> Workload {
+        let numel = self.numel();
+        let columns = self.column_count();
+        let rows = numel / columns.max(1);
+        let per_group = self.invocations_per_group();
+        let span = per_group * self.elements.0;
+        let busy = per_group.min(columns.div_ceil(self.elements.0));
+        let coverage = Coverage {
+            elements: numel,
+            groups: rows,
+            invocations: rows * per_group,
+            busy_invocations: rows * busy,
+            tail_groups: if columns.is_multiple_of(span) {
+                0
+            } else {
+                rows
+            },
+        };
+        self.finish(WorkgroupCount::new(rows as _, 1, 1), coverage)
+    }
+
+    /// A workgroup per tile of `size.x * elements.x` columns by `size.y * elements.y` rows,
+    /// leading dimensions batched along z. Tiles hanging over the edge are counted as tail groups.
+    pub fn tiled(self) -> Workload {
+        let numel = self.numel();
+        let (columns, rows) = (self.column_count(), self.row_count());
+        let batch = numel / (columns * rows).max(1);
+        let (size_x, size_y) = (self.size.0 as usize, self.size.1 as usize);
+        let tile = (size_x * self.elements.0, size_y * self.elements.1);
+        let (groups_x, groups_y) = (columns.div_ceil(tile.0), rows.div_ceil(tile.1));
+        let full = (columns / tile.0) * (rows / tile.1);
+        let busy = columns.div_ceil(self.elements.0) * rows.div_ceil(self.elements.1);
+        let groups = groups_x * groups_y * batch;
+        let coverage = Coverage {
+            elements: numel,
+            groups,
+            invocations: groups * self.invocations_per_group(),
+            busy_invocations: busy * batch,
+            tail_groups: (groups_x * groups_y - full) * batch,
+        };
+        let count = WorkgroupCount::new(groups_x as _, groups_y as _, batch as _);
+        self.finish(count, coverage)
+    }
+}
+
+/// # Coverage
+///
+/// How well a problem fills the dispatch a `WorkloadBuilder` derived for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Coverage {
+    pub elements: usize,
+    /// Workgroups requested, before any folding.
+    pub groups: usize,
+    pub invocations: usize,
+    /// Invocations handling at least one element.
+    pub busy_invocations: usize,
+    /// Workgroups the problem only partly covers.
+    pub tail_groups: usize,
+}
+
+impl Coverage {
+    pub fn idle_invocations(&self) -> usize {
+        self.invocations - self.busy_invocations
+    }
+
+    /// Fraction of dispatched invocations doing any work.
+    pub fn utilization(&self) -> f64 {
+        self.busy_invocations as f64 / self.invocations.max(1) as f64
+    }
+}
+
+impl std::fmt::Display for Coverage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} workgroups, {} of {} invocations idle ({:.1}% utilized), {} tail groups",
+            self.groups,
+            self.idle_invocations(),
+            self.invocations,
+            self.utilization() * 100.,
+            self.tail_groups
+        )
+    }
+}
+
 ///Used to determine which limit applies
 #[derive(Debug, Clone)]
 pub enum WorkloadDim {
@@ -67,6 +300,143 @@ impl Workload {
     pub const MAX_COMPUTE_WORKGROUPS_PER_DIMENSION: usize = 65535;
 
     pub fn ceil(num: usize, div: usize) -> usize {
-        (num + div - 1) / div
+        num.div_ceil(div)
+    }
+
+    /// Spreads a 1D count over `max_per_dim` across y, then z, as evenly as possible.
+    /// Kernels rebuild the linear workgroup index as `x + y * count.x + z * count.x * count.y`
+    /// (`linear_workgroup_id` in `lib/workgroup.wgsl`) and skip indices past the requested count,
+    /// as the folded count may round up. Counts already using y or z are left as they are.
+    pub fn fold(count: &WorkgroupCount, max_per_dim: u32) -> WorkgroupCount {
+        let (x, y, z) = count.as_tuple();
+        if x <= max_per_dim || y != 1 || z != 1 {
+            return count.clone();
+        }
+        let (n, max) = (x as u64, max_per_dim as u64);
+        let z = n.div_ceil(max * max);
+        let rest = n.div_ceil(z);
+        let y = rest.div_ceil(max);
+        let x = rest.div_ceil(y);
+        WorkgroupCount::new(x as _, y as _, z as _)
+    }
+}
+
+impl Workload {
+    /// Checks the dispatch against the portable limits above, no device required.
+    pub fn check(&self) -> anyhow::Result<()> {
+        let size = &self.size;
+        let max_size = (
+            Self::MAX_WORKGROUP_SIZE_X as u32,
+            Self::MAX_WORKGROUP_SIZE_Y as u32,
+            Self::MAX_WORKGROUP_SIZE_Z as u32,
+        );
+        if size.0 > max_size.0 || size.1 > max_size.1 || size.2 > max_size.2 {
+            anyhow::bail!("Workgroup size {:?} exceeds {:?}", size, max_size);
+        }
+        let (x, y, z) = self.count.as_tuple();
+        let max_count = Self::MAX_COMPUTE_WORKGROUPS_PER_DIMENSION as u32;
+        if x.max(y).max(z) > max_count {
+            anyhow::bail!(
+                "Workgroup count {:?} exceeds {} per dimension{}",
+                self.count,
+                max_count,
+                if y > 1 || z > 1 {
+                    ", only 1D counts are folded"
+                } else {
+                    ", kernels indexing with `linear_workgroup_id` can opt into folding"
+                }
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks the dispatch against the device limits, before it reaches wgpu validation.
+    pub fn check_limits(&self, limits: &wgpu::Limits) -> anyhow::Result<()> {
+        let size = &self.size;
+        let max_size = (
+            limits.max_compute_workgroup_size_x,
+            limits.max_compute_workgroup_size_y,
+            limits.max_compute_workgroup_size_z,
+        );
+        if size.0 > max_size.0 || size.1 > max_size.1 || size.2 > max_size.2 {
+            anyhow::bail!("Workgroup size {:?} exceeds {:?}", size, max_size);
+        }
+        if size.total() > limits.max_compute_invocations_per_workgroup {
+            anyhow::bail!(
+                "Workgroup size {:?} exceeds {} invocations",
+                size,
+                limits.max_compute_invocations_per_workgroup
+            );
+        }
+        let (x, y, z) = self.count.as_tuple();
+        if x.max(y).max(z) > limits.max_compute_workgroups_per_dimension {
+            anyhow::bail!(
+                "Workgroup count {:?} exceeds {} per dimension",
+                self.count,
+                limits.max_compute_workgroups_per_dimension
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{shape, Workload};
+
+    #[test]
+    pub fn oversized_counts_fold() {
+        let rows = 100_000;
+        let workload = Workload::folded(wgs![128, 1, 1], wgc![rows, 1, 1]);
+        assert!(workload.is_folded());
+        assert_eq!(workload.count().as_tuple(), (50_000, 2, 1));
+        assert!(workload.check().is_ok());
+
+        //Without opting in the count is dispatched as asked, and rejected
+        let unfolded = Workload::new(wgs![128, 1, 1], wgc![rows, 1, 1]);
+        assert!(!unfolded.is_folded());
+        assert!(unfolded.check().is_err());
+        let built = Workload::builder(&shape![rows as usize, 64], wgs![64, 1, 1]);
+        assert!(built.clone().rows().check().is_err());
+        assert_eq!(built.foldable().rows().count().as_tuple(), (50_000, 2, 1));
+
+        let max = Workload::MAX_COMPUTE_WORKGROUPS_PER_DIMENSION as u32;
+        let huge = Workload::fold(&wgc![u32::MAX, 1, 1], max);
+        let (x, y, z) = huge.as_tuple();
+        assert!(x.max(y).max(z) <= max && z > 1);
+        assert!(huge.total() >= u32::MAX as u64);
+
+        let small = Workload::folded(wgs![128, 1, 1], wgc![rows / 2, 1, 1]);
+        assert!(!small.is_folded());
+        let grid = Workload::folded(wgs![16, 16, 1], wgc![rows, 2, 1]);
+        assert!(grid.check().is_err());
+
+        //A device allowing more per dimension needs no fold, one allowing less folds further
+        assert_eq!(workload.folded_to(rows).count().as_tuple(), (rows, 1, 1));
+        assert_eq!(workload.folded_to(1000).count().as_tuple(), (1000, 100, 1));
+        assert_eq!(unfolded.folded_to(1000).count().as_tuple(), (rows, 1, 1));
+    }
+
+    #[test]
+    pub fn builder_mappings() {
+        //tfjs GEMM: 32x32 tiles, 4 columns by 4 rows per invocation, over a ragged edge
+        let gemm = Workload::builder(&shape![2, 100, 64], wgs![8, 8, 1])
+            .elements_per_invocation(4, 4)
+            .tiled();
+        assert_eq!(gemm.count().as_tuple(), (2, 4, 2));
+        let coverage = gemm.coverage().unwrap();
+        assert_eq!(coverage.tail_groups, 2 * 2);
+        assert_eq!(coverage.busy_invocations, 16 * 25 * 2);
+        assert_eq!(coverage.idle_invocations(), 16 * 32 * 2 - 16 * 25 * 2);
+
+        let rows = Workload::builder(&shape![1, 8, 100], wgs![128, 1, 1]).rows();
+        assert_eq!(rows.count().as_tuple(), (8, 1, 1));
+        assert_eq!(rows.coverage().unwrap().idle_invocations(), 8 * 28);
+
+        let linear = Workload::builder(&shape![1000], wgs![256, 1, 1])
+            .elements_per_invocation(4, 1)
+            .linear();
+        assert_eq!(linear.count().as_tuple(), (1, 1, 1));
+        assert_eq!(linear.coverage().unwrap().busy_invocations, 250);
     }
 }